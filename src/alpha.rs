@@ -0,0 +1,131 @@
+use image::{DynamicImage, GenericImageView, GrayImage, RgbImage};
+use image::imageops::{self, FilterType};
+use crate::CardGrid;
+use crate::layout::create_tile_samples;
+
+/* sample pixels less opaque than this don't count when matching */
+pub const ALPHA_THRESHOLD: u8 = 128;
+/* tiles with less of their area opaque than this are left empty */
+const MIN_OPAQUE_COVERAGE: f32 = 0.5_f32;
+
+/**
+ * the base image's alpha at the size of its sample image, none if it has no alpha at all
+ */
+pub fn create_alpha_image(base_image: &DynamicImage, sample_width: u32, sample_height: u32, cards_wide: u32, cards_tall: u32) -> Option<GrayImage> {
+	if !base_image.color().has_alpha() { return None; }
+
+	let resized = base_image.resize_exact(cards_wide * sample_width, cards_tall * sample_height, FilterType::Triangle).to_rgba8();
+	let (width, height) = resized.dimensions();
+
+	Some(GrayImage::from_raw(width, height, resized.pixels().map(|pixel| pixel[3]).collect::<Vec<u8>>()).unwrap())
+}
+
+/**
+ * the base image with the mask as its alpha, so only the mask's shape gets cards
+ * masks with alpha use it, others are inside wherever they're white
+ * whatever the base image already had transparent stays that way
+ */
+pub fn apply_mask(base_image: &DynamicImage, mask_image: &DynamicImage) -> DynamicImage {
+	let mask_alpha = mask_image.color().has_alpha();
+	let mask = mask_image.resize_exact(base_image.width(), base_image.height(), FilterType::Triangle).to_luma_alpha8();
+	let mut masked = base_image.to_rgba8();
+
+	for (pixel, mask_pixel) in masked.pixels_mut().zip(mask.pixels()) {
+		let inside = if mask_alpha { mask_pixel[1] } else { mask_pixel[0] };
+		pixel[3] = (pixel[3] as u32 * inside as u32 / 255) as u8;
+	}
+
+	DynamicImage::ImageRgba8(masked)
+}
+
+/**
+ * the base image's alpha, made once and reused for every grid whose tiles are counted or removed
+ */
+pub struct Shape {
+	alpha_image: GrayImage,
+	sample_width: u32,
+	sample_height: u32,
+}
+
+/**
+ * the base image's alpha sized so opaque_cells cells of its opaque part get about a sample's worth of pixels each,
+ * never bigger than the base image, none if it has no alpha at all
+ */
+pub fn create_shape(base_image: &DynamicImage, opaque_cells: u32, sample_width: u32, sample_height: u32) -> Option<Shape> {
+	if !base_image.color().has_alpha() { return None; }
+
+	let rgba = base_image.to_rgba8();
+	let (width, height) = rgba.dimensions();
+	let alpha_image = GrayImage::from_raw(width, height, rgba.pixels().map(|pixel| pixel[3]).collect::<Vec<u8>>()).unwrap();
+
+	/* the fewer pixels are opaque, the more cells the whole image is cut into to fit opaque_cells inside the shape */
+	let opaque = alpha_image.pixels().filter(|pixel| pixel[0] >= ALPHA_THRESHOLD).count().max(1) as f64;
+	let cell_pixels = opaque / opaque_cells.max(1) as f64;
+	let scale = ((sample_width * sample_height) as f64 / cell_pixels).sqrt().min(1.0_f64);
+
+	let alpha_image = if scale < 1.0_f64 {
+		let scaled_width = ((width as f64 * scale).round() as u32).max(1);
+		let scaled_height = ((height as f64 * scale).round() as u32).max(1);
+		imageops::resize(&alpha_image, scaled_width, scaled_height, FilterType::Triangle)
+	} else {
+		alpha_image
+	};
+
+	Some(Shape { alpha_image, sample_width, sample_height })
+}
+
+/**
+ * whether any of the shape is opaque enough to get a card, however small the tiles are made
+ */
+pub fn has_opaque_part(shape: &Shape) -> bool {
+	shape.alpha_image.pixels().any(|pixel| pixel[0] >= ALPHA_THRESHOLD)
+}
+
+/**
+ * whether each tile has enough of its part of the shape opaque to get a card
+ */
+fn opaque_tiles(shape: &Shape, card_grid: &CardGrid) -> Vec<bool> {
+	/* only the masks are looked at, so the colors can be left blank */
+	let sample_image = RgbImage::new(shape.alpha_image.width(), shape.alpha_image.height());
+
+	/* the same samples with and without the alpha masked out, the difference is what's transparent */
+	let shapes = create_tile_samples(&sample_image, None, card_grid, shape.sample_width, shape.sample_height);
+	let opaque = create_tile_samples(&sample_image, Some(&shape.alpha_image), card_grid, shape.sample_width, shape.sample_height);
+
+	shapes.iter().zip(opaque.iter()).map(|(shape, opaque)| {
+		let inside = shape.mask.iter().filter(|inside| **inside).count();
+		let opaque = opaque.mask.iter().filter(|opaque| **opaque).count();
+
+		inside > 0 && opaque as f32 >= inside as f32 * MIN_OPAQUE_COVERAGE
+	}).collect::<Vec<bool>>()
+}
+
+/**
+ * how many tiles remove_transparent_tiles would keep
+ */
+pub fn count_opaque_tiles(shape: Option<&Shape>, card_grid: &CardGrid) -> u32 {
+	match shape {
+		Some(shape) => opaque_tiles(shape, card_grid).iter().filter(|keep| **keep).count() as u32,
+		None => card_grid.tiles.len() as u32,
+	}
+}
+
+/**
+ * drops the tiles whose part of the base image is mostly transparent, so no card is picked or drawn there
+ */
+pub fn remove_transparent_tiles(shape: Option<&Shape>, card_grid: &mut CardGrid) {
+	let keep = match shape {
+		Some(shape) => opaque_tiles(shape, card_grid),
+		None => return,
+	};
+
+	let num_tiles = card_grid.tiles.len();
+	let mut keep = keep.iter();
+	card_grid.tiles.retain(|_| *keep.next().unwrap());
+
+	card_grid.grid.truncate(card_grid.tiles.len());
+	card_grid.variants.truncate(card_grid.tiles.len());
+	card_grid.costs.truncate(card_grid.tiles.len());
+
+	println!("Left {} mostly transparent tiles empty", num_tiles - card_grid.tiles.len());
+}
@@ -0,0 +1,374 @@
+use image::{RgbImage, EncodableLayout};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/* stored in the library folder, next to the cards it describes */
+pub const CACHE_FILE: &str = ".samples.cache";
+
+const CACHE_MAGIC: &[u8; 4] = b"MTGS";
+/* bump whenever the way samples are made changes, so old caches get thrown out */
+const CACHE_VERSION: u32 = 1;
+/* the fewest bytes an entry and a sample can take up, so counts can be checked against what's left of the file */
+const MIN_ENTRY_BYTES: usize = 4 + 8 + 8 + 8 + 3 + 4;
+const MIN_SAMPLE_BYTES: usize = 4 + 4 + 4;
+
+/**
+ * one stored sample of a card, made with the given size and crop window
+ */
+struct CachedSample {
+	width: u32,
+	height: u32,
+	window: f32,
+	bytes: Vec<u8>,
+}
+
+/**
+ * everything remembered about a single library file
+ * size and modified are checked first, hash only when those change
+ */
+struct CacheEntry {
+	size: u64,
+	modified: u64,
+	hash: u64,
+	average: [u8; 3],
+	samples: Vec<CachedSample>,
+}
+
+/**
+ * precomputed samples for a library folder, keyed by file name
+ */
+pub struct SampleCache {
+	dir: PathBuf,
+	entries: HashMap<String, CacheEntry>,
+	changed: bool,
+}
+
+/**
+ * 64 bit FNV-1a, stable between runs and builds unlike the std hasher
+ */
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+	let mut hash = 0xcbf29ce484222325_u64;
+
+	for byte in bytes {
+		hash ^= *byte as u64;
+		hash = hash.wrapping_mul(0x100000001b3);
+	}
+
+	hash
+}
+
+fn file_stamp(path: &Path) -> std::io::Result<(u64, u64)> {
+	let metadata = fs::metadata(path)?;
+
+	let modified = metadata.modified()?
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |duration| duration.as_nanos() as u64);
+
+	Ok((metadata.len(), modified))
+}
+
+fn cache_key(cache: &SampleCache, path: &Path) -> String {
+	path.strip_prefix(&cache.dir).unwrap_or(path).to_string_lossy().into_owned()
+}
+
+fn average_color(sample: &RgbImage) -> [u8; 3] {
+	let mut total = [0_u64; 3];
+
+	for pixel in sample.as_bytes().chunks(3) {
+		for channel in 0..3 {
+			total[channel] += pixel[channel] as u64;
+		}
+	}
+
+	let num_pixels = (sample.width() * sample.height()).max(1) as u64;
+
+	[(total[0] / num_pixels) as u8, (total[1] / num_pixels) as u8, (total[2] / num_pixels) as u8]
+}
+
+/* little endian readers and writers for the cache format */
+
+fn invalid_data(message: &str) -> std::io::Error {
+	std::io::Error::new(ErrorKind::InvalidData, message)
+}
+
+/**
+ * a count read from the file, as long as that many items of min_bytes each could still fit in what's left of it
+ */
+fn read_count(reader: &mut &[u8], min_bytes: usize) -> std::io::Result<usize> {
+	let count = read_u32(reader)? as usize;
+	if count > reader.len() / min_bytes { return Err(invalid_data("Cache count runs past the end of the file!")); }
+	Ok(count)
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+	let mut bytes = [0u8; 4];
+	reader.read_exact(&mut bytes)?;
+	Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+	let mut bytes = [0u8; 8];
+	reader.read_exact(&mut bytes)?;
+	Ok(u64::from_le_bytes(bytes))
+}
+
+/**
+ * lengths come from the file, so they're checked against what's left before anything is allocated
+ */
+fn read_bytes(reader: &mut &[u8], length: usize) -> std::io::Result<Vec<u8>> {
+	if length > reader.len() { return Err(invalid_data("Cache length runs past the end of the file!")); }
+
+	let mut bytes = vec![0u8; length];
+	reader.read_exact(&mut bytes)?;
+	Ok(bytes)
+}
+
+/**
+ * reads from the whole file in memory, anything that doesn't add up is invalid data
+ */
+fn read_cache(reader: &mut &[u8]) -> std::io::Result<HashMap<String, CacheEntry>> {
+	let magic = read_bytes(reader, 4)?;
+	if magic.as_slice() != CACHE_MAGIC || read_u32(reader)? != CACHE_VERSION {
+		return Err(invalid_data("Not a current sample cache!"));
+	}
+
+	let num_entries = read_count(reader, MIN_ENTRY_BYTES)?;
+	let mut entries = HashMap::with_capacity(num_entries);
+
+	for _ in 0..num_entries {
+		let name_length = read_u32(reader)? as usize;
+		let name = String::from_utf8(read_bytes(reader, name_length)?)
+			.map_err(|_| invalid_data("Bad file name!"))?;
+
+		let size = read_u64(reader)?;
+		let modified = read_u64(reader)?;
+		let hash = read_u64(reader)?;
+		let average = read_bytes(reader, 3)?;
+
+		let num_samples = read_count(reader, MIN_SAMPLE_BYTES)?;
+		let mut samples = Vec::with_capacity(num_samples);
+
+		for _ in 0..num_samples {
+			let width = read_u32(reader)?;
+			let height = read_u32(reader)?;
+			let window = f32::from_bits(read_u32(reader)?);
+			let length = (width as usize).checked_mul(height as usize).and_then(|pixels| pixels.checked_mul(3))
+				.ok_or_else(|| invalid_data("Sample size overflows!"))?;
+			let bytes = read_bytes(reader, length)?;
+
+			samples.push(CachedSample { width, height, window, bytes });
+		}
+
+		entries.insert(name, CacheEntry { size, modified, hash, average: [average[0], average[1], average[2]], samples });
+	}
+
+	Ok(entries)
+}
+
+fn write_cache(writer: &mut impl Write, entries: &HashMap<String, CacheEntry>) -> std::io::Result<()> {
+	writer.write_all(CACHE_MAGIC)?;
+	writer.write_all(&CACHE_VERSION.to_le_bytes())?;
+	writer.write_all(&(entries.len() as u32).to_le_bytes())?;
+
+	for (name, entry) in entries {
+		writer.write_all(&(name.len() as u32).to_le_bytes())?;
+		writer.write_all(name.as_bytes())?;
+
+		writer.write_all(&entry.size.to_le_bytes())?;
+		writer.write_all(&entry.modified.to_le_bytes())?;
+		writer.write_all(&entry.hash.to_le_bytes())?;
+		writer.write_all(&entry.average)?;
+
+		writer.write_all(&(entry.samples.len() as u32).to_le_bytes())?;
+
+		for sample in &entry.samples {
+			writer.write_all(&sample.width.to_le_bytes())?;
+			writer.write_all(&sample.height.to_le_bytes())?;
+			writer.write_all(&sample.window.to_bits().to_le_bytes())?;
+			writer.write_all(&sample.bytes)?;
+		}
+	}
+
+	Ok(())
+}
+
+/**
+ * a missing or unreadable cache just starts out empty
+ */
+pub fn load_sample_cache(dir_path: &str) -> SampleCache {
+	let dir = PathBuf::from(dir_path);
+
+	let entries = fs::read(dir.join(CACHE_FILE))
+		.and_then(|bytes| read_cache(&mut bytes.as_slice()))
+		.unwrap_or_else(|_| HashMap::new());
+
+	SampleCache { dir, entries, changed: false }
+}
+
+/**
+ * only writes when something was added or thrown out
+ * written to a temporary file first so an interrupted save can't corrupt the cache
+ */
+pub fn save_sample_cache(cache: &mut SampleCache) -> std::io::Result<()> {
+	if !cache.changed { return Ok(()); }
+
+	let final_path = cache.dir.join(CACHE_FILE);
+	let temp_path = cache.dir.join(format!("{}.tmp", CACHE_FILE));
+
+	{
+		let mut writer = BufWriter::new(fs::File::create(&temp_path)?);
+		write_cache(&mut writer, &cache.entries)?;
+		writer.flush()?;
+	}
+
+	fs::rename(temp_path, final_path)?;
+	cache.changed = false;
+
+	Ok(())
+}
+
+/**
+ * drops entries for files that are gone or whose contents changed
+ * a file that was only touched keeps its samples
+ */
+pub fn validate_cache(cache: &mut SampleCache, paths: &[&Path]) {
+	let mut entries = HashMap::with_capacity(paths.len());
+
+	for path in paths {
+		let key = cache_key(cache, path);
+		let (size, modified) = match file_stamp(path) {
+			Ok(stamp) => stamp,
+			Err(_) => continue,
+		};
+
+		match cache.entries.remove(&key) {
+			Some(entry) if entry.size == size && entry.modified == modified => {
+				entries.insert(key, entry);
+			},
+			Some(mut entry) => {
+				let hash = fs::read(path).map(|bytes| hash_bytes(&bytes)).unwrap_or(0);
+
+				if entry.hash == hash {
+					entry.modified = modified;
+					entry.size = size;
+					entries.insert(key, entry);
+				}
+
+				cache.changed = true;
+			},
+			None => {},
+		}
+	}
+
+	if !cache.entries.is_empty() { cache.changed = true; }
+
+	cache.entries = entries;
+}
+
+pub fn has_sample(cache: &SampleCache, path: &Path, width: u32, height: u32, window: f32) -> bool {
+	cache.entries.get(&cache_key(cache, path))
+		.is_some_and(|entry| entry.samples.iter().any(|sample| sample.width == width && sample.height == height && sample.window == window))
+}
+
+pub fn lookup_sample(cache: &SampleCache, path: &Path, width: u32, height: u32, window: f32) -> Option<RgbImage> {
+	let entry = cache.entries.get(&cache_key(cache, path))?;
+
+	entry.samples.iter()
+		.find(|sample| sample.width == width && sample.height == height && sample.window == window)
+		.and_then(|sample| RgbImage::from_raw(width, height, sample.bytes.clone()))
+}
+
+/**
+ * the first sample stored for a file also sets its average color
+ */
+pub fn store_sample(cache: &mut SampleCache, path: &Path, window: f32, sample: &RgbImage) {
+	let key = cache_key(cache, path);
+
+	if !cache.entries.contains_key(&key) {
+		let (size, modified) = match file_stamp(path) {
+			Ok(stamp) => stamp,
+			Err(_) => return,
+		};
+		let hash = fs::read(path).map(|bytes| hash_bytes(&bytes)).unwrap_or(0);
+
+		cache.entries.insert(key.clone(), CacheEntry { size, modified, hash, average: average_color(sample), samples: Vec::new() });
+	}
+
+	let entry = cache.entries.get_mut(&key).unwrap();
+
+	entry.samples.push(CachedSample {
+		width: sample.width(),
+		height: sample.height(),
+		window,
+		bytes: sample.as_bytes().to_vec(),
+	});
+
+	cache.changed = true;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn test_entries() -> HashMap<String, CacheEntry> {
+		let mut entries = HashMap::new();
+
+		entries.insert("a.png".to_string(), CacheEntry {
+			size: 1234,
+			modified: 5678,
+			hash: hash_bytes(b"a"),
+			average: [1, 2, 3],
+			samples: vec![
+				CachedSample { width: 2, height: 3, window: 0.5_f32, bytes: (0..18).collect() },
+				CachedSample { width: 1, height: 1, window: 0_f32, bytes: vec![7, 8, 9] },
+			],
+		});
+		entries.insert("b.png".to_string(), CacheEntry { size: 1, modified: 2, hash: 3, average: [0, 0, 0], samples: Vec::new() });
+
+		entries
+	}
+
+	#[test]
+	fn round_trip() {
+		let entries = test_entries();
+		let mut bytes = Vec::new();
+		write_cache(&mut bytes, &entries).unwrap();
+
+		let read = read_cache(&mut bytes.as_slice()).unwrap();
+		assert_eq!(read.len(), entries.len());
+
+		for (name, entry) in &entries {
+			let read_entry = &read[name];
+			assert_eq!((read_entry.size, read_entry.modified, read_entry.hash, read_entry.average), (entry.size, entry.modified, entry.hash, entry.average));
+			assert_eq!(read_entry.samples.len(), entry.samples.len());
+
+			for (read_sample, sample) in read_entry.samples.iter().zip(entry.samples.iter()) {
+				assert_eq!((read_sample.width, read_sample.height, read_sample.window), (sample.width, sample.height, sample.window));
+				assert_eq!(read_sample.bytes, sample.bytes);
+			}
+		}
+	}
+
+	#[test]
+	fn huge_counts_are_invalid() {
+		let mut bytes = Vec::new();
+		bytes.extend_from_slice(CACHE_MAGIC);
+		bytes.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+		bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+		bytes.extend_from_slice(&[0xff; 64]);
+
+		assert_eq!(read_cache(&mut bytes.as_slice()).err().unwrap().kind(), ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn truncated_cache_is_an_error() {
+		let mut bytes = Vec::new();
+		write_cache(&mut bytes, &test_entries()).unwrap();
+		bytes.truncate(bytes.len() - 1);
+
+		assert!(read_cache(&mut bytes.as_slice()).is_err());
+	}
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use crate::library::LibraryCard;
+use crate::metadata::{CardMetadata, card_metadata};
+
+/* scryfall id -> how many are owned, stored in the library folder */
+pub const COLLECTION_FILE: &str = ".collection.json";
+
+/* longest set code, anything longer or with spaces is taken as a set name */
+const MAX_SET_CODE: usize = 6;
+
+/**
+ * one line of a collection export
+ * anything the export doesn't have is left empty
+ */
+pub struct CollectionEntry {
+	pub name: String,
+	pub set: String,
+	pub collector_number: String,
+	pub quantity: u32,
+	pub scryfall_id: Option<String>,
+}
+
+/**
+ * the first of names found in the headers
+ */
+fn find_column(headers: &[String], names: &[&str]) -> Option<usize> {
+	names.iter().find_map(|name| headers.iter().position(|header| header == name))
+}
+
+/**
+ * headers lowercased with everything but letters and digits dropped,
+ * so "Collector Number", "collector_number" and "CollectorNumber" are all the same
+ */
+fn normalize_header(header: &str) -> String {
+	header.chars().filter(|character| character.is_ascii_alphanumeric()).collect::<String>().to_lowercase()
+}
+
+/**
+ * reads a collection export, like moxfield, manabox, deckbox, archidekt or dragon shield ones,
+ * or just a name,set,collector_number,quantity file
+ */
+pub fn parse_collection(path: &Path) -> Result<Vec<CollectionEntry>, Box<dyn std::error::Error>> {
+	let mut reader = csv::ReaderBuilder::new().flexible(true).trim(csv::Trim::All).from_path(path)?;
+	let headers = reader.headers()?.iter().map(normalize_header).collect::<Vec<String>>();
+
+	let name_column = find_column(&headers, &["name", "cardname"]).ok_or("Collection has no name column!")?;
+	let quantity_column = find_column(&headers, &["quantity", "count", "qty", "amount"]);
+	let set_column = find_column(&headers, &["setcode", "editioncode", "set", "edition"]);
+	let number_column = find_column(&headers, &["collectornumber", "cardnumber", "number", "cn"]);
+	let id_column = find_column(&headers, &["scryfallid"]);
+
+	let mut entries = Vec::new();
+
+	for record in reader.records() {
+		let record = record?;
+		let field = |column: Option<usize>| column.and_then(|column| record.get(column)).unwrap_or("").to_string();
+
+		let name = field(Some(name_column));
+		if name.is_empty() { continue; }
+
+		entries.push(CollectionEntry {
+			name,
+			set: field(set_column),
+			collector_number: field(number_column),
+			/* exports without a quantity list every copy on its own line */
+			quantity: quantity_column.map_or(Some(1), |_| field(quantity_column).parse::<u32>().ok()).ok_or("Collection quantity isn't a number!")?,
+			scryfall_id: Some(field(id_column)).filter(|id| !id.is_empty()),
+		});
+	}
+
+	Ok(entries)
+}
+
+/**
+ * whether the export gave a set code rather than a set name
+ */
+pub fn is_set_code(set: &str) -> bool {
+	!set.is_empty() && set.len() <= MAX_SET_CODE && !set.contains(' ')
+}
+
+/**
+ * the scryfall id of a library card that is the entry's printing
+ * entries without a collector number take any printing of that name from that set, or from any set without one
+ * a collector number without a set is only unique together with the name
+ */
+pub fn find_library_card(entry: &CollectionEntry, metadata: &HashMap<String, CardMetadata>) -> Option<String> {
+	let set_matches = |card: &CardMetadata| entry.set.is_empty()
+		|| card.set.eq_ignore_ascii_case(&entry.set)
+		|| card.set_name.eq_ignore_ascii_case(&entry.set);
+
+	metadata.values().find(|card| match &entry.scryfall_id {
+		Some(id) => card.id.eq_ignore_ascii_case(id),
+		None if !entry.collector_number.is_empty() => set_matches(card)
+			&& card.collector_number.eq_ignore_ascii_case(&entry.collector_number)
+			&& (!entry.set.is_empty() || card.name.eq_ignore_ascii_case(&entry.name)),
+		None => set_matches(card) && card.name.eq_ignore_ascii_case(&entry.name),
+	}).map(|card| card.id.clone())
+}
+
+/**
+ * a missing collection means none has been imported
+ */
+pub fn load_collection(dir_path: &str) -> Option<HashMap<String, u32>> {
+	fs::read(Path::new(dir_path).join(COLLECTION_FILE)).ok()
+		.and_then(|bytes| serde_json::from_slice(&bytes).ok())
+}
+
+pub fn save_collection(dir_path: &str, collection: &HashMap<String, u32>) -> std::io::Result<()> {
+	fs::write(Path::new(dir_path).join(COLLECTION_FILE), serde_json::to_vec_pretty(collection)?)
+}
+
+/**
+ * drops every card that isn't owned, keeping one file per scryfall id
+ * returns how many times each remaining card can be used, in the same order
+ */
+pub fn restrict_to_collection(card_images: &mut Vec<LibraryCard>, collection: &HashMap<String, u32>, metadata: &HashMap<String, CardMetadata>) -> Vec<u32> {
+	let mut usage_caps: HashMap<&str, u32> = HashMap::new();
+	let library_size = card_images.len();
+
+	card_images.retain(|card| {
+		match card_metadata(metadata, &card.path).and_then(|card| collection.get_key_value(&card.id)) {
+			Some((id, quantity)) if *quantity > 0 && !usage_caps.contains_key(id.as_str()) => {
+				usage_caps.insert(id.as_str(), *quantity);
+				true
+			},
+			_ => false,
+		}
+	});
+
+	println!(
+		"Using {} owned cards out of {} in the library, {} owned cards have no image",
+		card_images.len(),
+		library_size,
+		collection.len().saturating_sub(usage_caps.len()),
+	);
+
+	card_images.iter()
+		.map(|card| usage_caps[card_metadata(metadata, &card.path).unwrap().id.as_str()])
+		.collect::<Vec<u32>>()
+}
+
+/**
+ * each owned card repeated for every copy owned, so that new can place each copy once
+ */
+pub fn expand_owned_copies(card_images: &mut Vec<LibraryCard>, usage_caps: &[u32]) {
+	let owned = card_images.clone();
+
+	for (card, usage_cap) in owned.iter().zip(usage_caps.iter()) {
+		for _ in 1..*usage_cap {
+			card_images.push(card.clone());
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn test_card(id: &str, name: &str, set: &str, collector_number: &str) -> (String, CardMetadata) {
+		(format!("{}.png", id), CardMetadata {
+			id: id.to_string(),
+			name: name.to_string(),
+			set: set.to_string(),
+			set_name: format!("{} set", set),
+			collector_number: collector_number.to_string(),
+			artist: None,
+			scryfall_uri: String::new(),
+		})
+	}
+
+	fn test_entry(name: &str, set: &str, collector_number: &str) -> CollectionEntry {
+		CollectionEntry { name: name.to_string(), set: set.to_string(), collector_number: collector_number.to_string(), quantity: 1, scryfall_id: None }
+	}
+
+	fn write_collection(name: &str, contents: &str) -> std::path::PathBuf {
+		let path = std::env::temp_dir().join(format!("mtg_resample_{}_{}.csv", name, std::process::id()));
+		fs::write(&path, contents).unwrap();
+		path
+	}
+
+	#[test]
+	fn collector_number_without_set_needs_the_name() {
+		let metadata = vec![
+			test_card("a", "Llanowar Elves", "dom", "168"),
+			test_card("b", "Giant Growth", "m10", "168"),
+		].into_iter().collect::<HashMap<String, CardMetadata>>();
+
+		assert_eq!(find_library_card(&test_entry("Giant Growth", "", "168"), &metadata), Some("b".to_string()));
+		assert_eq!(find_library_card(&test_entry("Llanowar Elves", "", "168"), &metadata), Some("a".to_string()));
+		assert_eq!(find_library_card(&test_entry("Shock", "", "168"), &metadata), None);
+	}
+
+	#[test]
+	fn collector_number_with_set_ignores_the_name() {
+		let metadata = vec![test_card("a", "Llanowar Elves", "dom", "168")].into_iter().collect::<HashMap<String, CardMetadata>>();
+
+		/* exports sometimes name double faced cards differently */
+		assert_eq!(find_library_card(&test_entry("Llanowar Elves // Something", "DOM", "168"), &metadata), Some("a".to_string()));
+		assert_eq!(find_library_card(&test_entry("Llanowar Elves", "m19", "168"), &metadata), None);
+	}
+
+	#[test]
+	fn parses_export_headers() {
+		let path = write_collection("headers", "Count,Name,Edition,Collector Number,Scryfall ID\n4,Shock,M19,156,abc\n,,,,\n1,Opt,Dominaria,60,\n");
+		let entries = parse_collection(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		assert_eq!(entries.len(), 2);
+		assert_eq!((entries[0].name.as_str(), entries[0].set.as_str(), entries[0].collector_number.as_str(), entries[0].quantity), ("Shock", "M19", "156", 4));
+		assert_eq!(entries[0].scryfall_id, Some("abc".to_string()));
+		assert_eq!((entries[1].set.as_str(), entries[1].quantity, entries[1].scryfall_id.is_none()), ("Dominaria", 1, true));
+	}
+
+	#[test]
+	fn every_line_is_one_copy_without_quantities() {
+		let path = write_collection("no_quantity", "card_name,set_code\nShock,m19\nShock,m19\n");
+		let entries = parse_collection(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		assert_eq!(entries.iter().map(|entry| entry.quantity).collect::<Vec<u32>>(), vec![1, 1]);
+		assert_eq!(entries[0].set, "m19");
+	}
+
+	#[test]
+	fn bad_quantities_and_missing_names_are_errors() {
+		let path = write_collection("bad_quantity", "name,quantity\nShock,lots\n");
+		assert!(parse_collection(&path).is_err());
+		fs::remove_file(&path).unwrap();
+
+		let path = write_collection("no_name", "set,quantity\nm19,1\n");
+		assert!(parse_collection(&path).is_err());
+		fs::remove_file(&path).unwrap();
+	}
+}
@@ -0,0 +1,173 @@
+use image::{DynamicImage, GenericImageView, RgbImage, Rgb, EncodableLayout};
+use image::imageops::FilterType;
+use crate::crop_card;
+use crate::window::{window_rect, crop_window};
+
+/* letterboxed images are padded with this color */
+const LETTERBOX_COLOR: [u8; 3] = [0, 0, 0];
+/* how many window positions are scored when looking for the best crop */
+const CROP_STEPS: u32 = 16;
+/* images are scored at about this many pixels along their long side */
+const CROP_SCORE_SIZE: u32 = 128;
+/* how much a pixel's saturation counts for next to its edge strength */
+const SATURATION_WEIGHT: f32 = 0.5_f32;
+
+/**
+ * how an image of any aspect is brought to the tile aspect
+ */
+#[derive(Clone, Copy, PartialEq)]
+pub enum CropStrategy {
+	/* cut equally off both sides */
+	Center,
+	/* keep the part with the most going on in it */
+	Entropy,
+	/* keep the part with the strongest edges and colors, usually the subject */
+	Attention,
+	/* keep everything and pad the spare space */
+	Letterbox,
+}
+
+pub fn parse_crop_strategy(name: &str) -> Option<CropStrategy> {
+	match name {
+		"center" => Some(CropStrategy::Center),
+		"entropy" => Some(CropStrategy::Entropy),
+		"attention" => Some(CropStrategy::Attention),
+		"letterbox" => Some(CropStrategy::Letterbox),
+		_ => None,
+	}
+}
+
+/**
+ * what's stored in the library for an imported image, always cut or padded to the card aspect
+ */
+pub fn crop_with_strategy(image: DynamicImage, card_aspect: f32, strategy: CropStrategy) -> DynamicImage {
+	match strategy {
+		CropStrategy::Center => crop_card(image, card_aspect),
+		CropStrategy::Entropy => {
+			let window = best_window(&image, card_aspect, entropy_score);
+			crop_window(&image, card_aspect, window)
+		},
+		CropStrategy::Attention => {
+			let window = best_window(&image, card_aspect, attention_score);
+			crop_window(&image, card_aspect, window)
+		},
+		CropStrategy::Letterbox => letterbox(&image, card_aspect),
+	}
+}
+
+/**
+ * what's stored in the library for a pulled card
+ * a center crop is what the middle window cuts when the card is loaded anyway,
+ * so the whole art is kept and other windows have room to slide along it,
+ * the other strategies pick their crop once here
+ */
+pub fn library_image(image: DynamicImage, card_aspect: f32, strategy: CropStrategy) -> DynamicImage {
+	match strategy {
+		CropStrategy::Center => image,
+		_ => crop_with_strategy(image, card_aspect, strategy),
+	}
+}
+
+/**
+ * shannon entropy of the brightness histogram, in bits
+ */
+fn entropy_score(image: &RgbImage) -> f32 {
+	let mut counts = [0_u32; 256];
+
+	for pixel in image.as_bytes().chunks(3) {
+		let brightness = (pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000;
+		counts[brightness as usize] += 1;
+	}
+
+	let total = (image.width() * image.height()).max(1) as f32;
+
+	counts.iter()
+		.filter(|count| **count > 0)
+		.map(|count| {
+			let probability = *count as f32 / total;
+			-probability * probability.log2()
+		})
+		.sum()
+}
+
+/**
+ * average edge energy plus saturation
+ * edges are the brightness differences to the right and below each pixel
+ */
+fn attention_score(image: &RgbImage) -> f32 {
+	let width = image.width() as usize;
+	let height = image.height() as usize;
+
+	let brightness = image.as_bytes().chunks(3)
+		.map(|pixel| pixel[0] as f32 * 0.299_f32 + pixel[1] as f32 * 0.587_f32 + pixel[2] as f32 * 0.114_f32)
+		.collect::<Vec<f32>>();
+
+	let mut total = 0.0_f32;
+
+	for (index, pixel) in image.as_bytes().chunks(3).enumerate() {
+		let (x, y) = (index % width, index / width);
+
+		let edge_x = if x + 1 < width { (brightness[index + 1] - brightness[index]).abs() } else { 0.0_f32 };
+		let edge_y = if y + 1 < height { (brightness[index + width] - brightness[index]).abs() } else { 0.0_f32 };
+
+		let saturation = (pixel.iter().max().unwrap() - pixel.iter().min().unwrap()) as f32;
+
+		total += edge_x + edge_y + saturation * SATURATION_WEIGHT;
+	}
+
+	total / (width * height).max(1) as f32
+}
+
+/**
+ * the window, as used by crop_window, whose crop scores highest
+ * scoring is done on a small copy of the image
+ */
+pub fn best_window(image: &DynamicImage, card_aspect: f32, score: fn(&RgbImage) -> f32) -> f32 {
+	let scale = CROP_SCORE_SIZE as f32 / image.width().max(image.height()) as f32;
+	let small_image = if scale < 1.0_f32 {
+		image.resize_exact(
+			((image.width() as f32 * scale).round() as u32).max(1),
+			((image.height() as f32 * scale).round() as u32).max(1),
+			FilterType::Triangle,
+		)
+	} else {
+		image.clone()
+	};
+
+	let mut best_score = f32::MIN;
+	let mut best_window = 0.5_f32;
+
+	for step in 0..=CROP_STEPS {
+		let window = step as f32 / CROP_STEPS as f32;
+		let current_score = score(&crop_window(&small_image, card_aspect, window).to_rgb8());
+
+		/* ties go to the window closest to the center */
+		if current_score > best_score || (current_score == best_score && (window - 0.5_f32).abs() < (best_window - 0.5_f32).abs()) {
+			best_score = current_score;
+			best_window = window;
+		}
+	}
+
+	best_window
+}
+
+/**
+ * the whole image, centered on a card_aspect canvas
+ */
+fn letterbox(image: &DynamicImage, card_aspect: f32) -> DynamicImage {
+	let width = image.width() as f32;
+	let height = image.height() as f32;
+
+	/* the image is the window of the canvas, so the canvas is the other way around */
+	let (canvas_width, canvas_height) = if width / height > card_aspect {
+		(width, width / card_aspect)
+	} else {
+		(height * card_aspect, height)
+	};
+	let (offset_x, offset_y, _, _) = window_rect(canvas_width, canvas_height, width / height, 0.5_f32);
+
+	let mut canvas = RgbImage::from_pixel(canvas_width.round() as u32, canvas_height.round() as u32, Rgb(LETTERBOX_COLOR));
+	image::imageops::overlay(&mut canvas, &image.to_rgb8(), offset_x.round() as u32, offset_y.round() as u32);
+
+	DynamicImage::ImageRgb8(canvas)
+}
@@ -0,0 +1,289 @@
+use image::{RgbImage, ColorType};
+use image::codecs::jpeg::JpegEncoder;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/* deep zoom tiles are 254 pixels plus a pixel of overlap on each side, 256 in all */
+const DZI_TILE_SIZE: u32 = 254;
+const DZI_OVERLAP: u32 = 1;
+const IIIF_TILE_SIZE: u32 = 256;
+
+/**
+ * how a pyramid is cut into tiles and where they are saved
+ */
+#[derive(Clone, Copy, PartialEq)]
+pub enum PyramidKind {
+	/* name.dzi next to name_files/level/column_row.jpg */
+	DeepZoom,
+	/* a folder of info.json and region/size/rotation/quality.jpg tiles */
+	Iiif,
+}
+
+/**
+ * one level of the pyramid, filled a row at a time
+ * only the rows still needed by tiles that haven't been written are kept
+ */
+struct PyramidLevel {
+	width: u32,
+	height: u32,
+	/* rgb rows starting at first_row */
+	rows: Vec<u8>,
+	first_row: u32,
+	/* how many rows have come in so far */
+	next_row: u32,
+	/* the next row of tiles to write */
+	tile_row: u32,
+	/* waiting for the row below it to be halved into the next level */
+	pending: Option<Vec<u8>>,
+}
+
+struct PyramidTarget<'a> {
+	kind: PyramidKind,
+	path: &'a Path,
+	tile_size: u32,
+	overlap: u32,
+	quality: u8,
+	/* full resolution size */
+	width: u32,
+	height: u32,
+	/* deep zoom numbers levels from the 1x1 one up */
+	max_level: u32,
+}
+
+fn deep_zoom_files(path: &Path) -> PathBuf {
+	let stem = path.file_stem().unwrap().to_string_lossy();
+	path.with_file_name(format!("{}_files", stem))
+}
+
+/**
+ * averages two rows into one row of the level below, half as wide
+ */
+fn halve_rows(top: &[u8], bottom: &[u8], width: u32) -> Vec<u8> {
+	let half_width = width.div_ceil(2);
+	let mut half = Vec::with_capacity((half_width * 3) as usize);
+
+	for x in 0..half_width {
+		let left = (x * 2) as usize;
+		let right = ((x * 2 + 1).min(width - 1)) as usize;
+
+		for channel in 0..3 {
+			let total = top[left * 3 + channel] as u32 + top[right * 3 + channel] as u32
+				+ bottom[left * 3 + channel] as u32 + bottom[right * 3 + channel] as u32;
+
+			half.push(((total + 2) / 4) as u8);
+		}
+	}
+
+	half
+}
+
+/**
+ * where a tile goes, iiif's single tile level is asked for as the full region so it's saved there too
+ */
+fn tile_paths(target: &PyramidTarget, level_index: u32, column: u32, row: u32, tile: &RgbImage) -> Vec<PathBuf> {
+	match target.kind {
+		PyramidKind::DeepZoom => vec![deep_zoom_files(target.path)
+			.join((target.max_level - level_index).to_string())
+			.join(format!("{}_{}.jpg", column, row))],
+		PyramidKind::Iiif => {
+			/* regions are in full resolution pixels */
+			let region_size = target.tile_size << level_index;
+			let (region_x, region_y) = (column * region_size, row * region_size);
+			let size = format!("{},", tile.width());
+
+			let region_path = target.path
+				.join(format!(
+					"{},{},{},{}",
+					region_x,
+					region_y,
+					region_size.min(target.width - region_x),
+					region_size.min(target.height - region_y),
+				))
+				.join(&size)
+				.join("0")
+				.join("default.jpg");
+
+			if tile.width() < target.tile_size && tile.height() < target.tile_size && column == 0 && row == 0 {
+				/* viewers ask for the full size by name when it's the whole image */
+				let full_size = if level_index == 0 { "full" } else { size.as_str() };
+				vec![region_path, target.path.join("full").join(full_size).join("0").join("default.jpg")]
+			} else {
+				vec![region_path]
+			}
+		},
+	}
+}
+
+fn save_tile(target: &PyramidTarget, level_index: u32, column: u32, row: u32, tile: &RgbImage) -> std::io::Result<()> {
+	let mut encoded = Vec::new();
+	JpegEncoder::new_with_quality(&mut encoded, target.quality)
+		.encode(tile.as_raw(), tile.width(), tile.height(), ColorType::Rgb8)
+		.map_err(|err| std::io::Error::other(err.to_string()))?;
+
+	for tile_path in tile_paths(target, level_index, column, row, tile) {
+		fs::create_dir_all(tile_path.parent().unwrap())?;
+		fs::write(tile_path, &encoded)?;
+	}
+
+	Ok(())
+}
+
+/**
+ * writes every row of tiles whose rows have all come in
+ */
+fn write_ready_tiles(target: &PyramidTarget, level: &mut PyramidLevel, level_index: u32) -> std::io::Result<()> {
+	let tile_size = target.tile_size;
+	let overlap = target.overlap;
+
+	loop {
+		let from_y = (level.tile_row * tile_size).saturating_sub(overlap);
+		let to_y = ((level.tile_row + 1) * tile_size + overlap).min(level.height);
+
+		if level.tile_row * tile_size >= level.height || level.next_row < to_y { return Ok(()); }
+
+		let columns = level.width.div_ceil(tile_size);
+
+		for column in 0..columns {
+			let from_x = (column * tile_size).saturating_sub(overlap);
+			let to_x = ((column + 1) * tile_size + overlap).min(level.width);
+
+			let mut tile_bytes = Vec::with_capacity((to_x - from_x) as usize * (to_y - from_y) as usize * 3);
+
+			for y in from_y..to_y {
+				let row_start = ((y - level.first_row) as usize * level.width as usize + from_x as usize) * 3;
+				tile_bytes.extend_from_slice(&level.rows[row_start..row_start + (to_x - from_x) as usize * 3]);
+			}
+
+			let tile = RgbImage::from_raw(to_x - from_x, to_y - from_y, tile_bytes).unwrap();
+			save_tile(target, level_index, column, level.tile_row, &tile)?;
+		}
+
+		level.tile_row += 1;
+
+		/* the next row of tiles starts overlap rows above its own top */
+		let keep_from = (level.tile_row * tile_size).saturating_sub(overlap).min(level.next_row);
+		level.rows.drain(..((keep_from - level.first_row) as usize * level.width as usize * 3));
+		level.first_row = keep_from;
+	}
+}
+
+/**
+ * adds a row to a level, writing any tiles it completes, and passes it down the pyramid
+ */
+fn push_row(target: &PyramidTarget, levels: &mut Vec<PyramidLevel>, level_index: usize, row: Vec<u8>) -> std::io::Result<()> {
+	let halved = {
+		let level = &mut levels[level_index];
+
+		level.rows.extend_from_slice(&row);
+		level.next_row += 1;
+
+		write_ready_tiles(target, level, level_index as u32)?;
+
+		let last_row = level.next_row == level.height;
+
+		match level.pending.take() {
+			Some(top) => Some(halve_rows(&top, &row, level.width)),
+			/* an odd row at the bottom is halved with itself */
+			None if last_row => Some(halve_rows(&row, &row, level.width)),
+			None => {
+				level.pending = Some(row);
+				None
+			},
+		}
+	};
+
+	match halved {
+		Some(halved) if level_index + 1 < levels.len() => push_row(target, levels, level_index + 1, halved),
+		_ => Ok(()),
+	}
+}
+
+fn write_descriptor(target: &PyramidTarget, num_levels: u32) -> std::io::Result<()> {
+	match target.kind {
+		PyramidKind::DeepZoom => fs::write(target.path, format!(
+			concat!(
+				"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+				"<Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" Format=\"jpg\" Overlap=\"{}\" TileSize=\"{}\">\n",
+				"\t<Size Width=\"{}\" Height=\"{}\"/>\n",
+				"</Image>\n",
+			),
+			target.overlap, target.tile_size, target.width, target.height,
+		)),
+		PyramidKind::Iiif => {
+			let scale_factors = (0..num_levels).map(|level| (1u32 << level).to_string()).collect::<Vec<String>>().join(", ");
+			/* relative, so the folder can be served from anywhere next to the page showing it */
+			let id = target.path.file_name().unwrap().to_string_lossy().into_owned();
+
+			fs::write(target.path.join("info.json"), format!(
+				concat!(
+					"{{\n",
+					"\t\"@context\": \"http://iiif.io/api/image/2/context.json\",\n",
+					"\t\"@id\": \"{}\",\n",
+					"\t\"protocol\": \"http://iiif.io/api/image\",\n",
+					"\t\"width\": {},\n",
+					"\t\"height\": {},\n",
+					"\t\"tiles\": [{{ \"width\": {}, \"scaleFactors\": [{}] }}],\n",
+					"\t\"profile\": [\"http://iiif.io/api/image/2/level0.json\"]\n",
+					"}}\n",
+				),
+				id, target.width, target.height, target.tile_size, scale_factors,
+			))
+		},
+	}
+}
+
+/**
+ * renders straight into a tile pyramid, a strip at a time
+ * each level keeps about a row of tiles in memory, never the whole image
+ */
+pub fn save_pyramid(
+	kind: PyramidKind,
+	path: &Path,
+	quality: u8,
+	width: u32,
+	height: u32,
+	strip_height: u32,
+	draw_strip: &mut dyn FnMut(u32, u32) -> Vec<u8>,
+) -> std::io::Result<()> {
+	let (tile_size, overlap) = match kind {
+		PyramidKind::DeepZoom => (DZI_TILE_SIZE, DZI_OVERLAP),
+		PyramidKind::Iiif => (IIIF_TILE_SIZE, 0),
+	};
+
+	/* deep zoom goes all the way down to 1x1, iiif stops once a level fits in a tile with room to spare, like viewers count them */
+	let mut levels: Vec<PyramidLevel> = Vec::new();
+	let (mut level_width, mut level_height) = (width, height);
+
+	loop {
+		levels.push(PyramidLevel { width: level_width, height: level_height, rows: Vec::new(), first_row: 0, next_row: 0, tile_row: 0, pending: None });
+
+		let done = match kind {
+			PyramidKind::DeepZoom => level_width == 1 && level_height == 1,
+			PyramidKind::Iiif => level_width < tile_size && level_height < tile_size,
+		};
+		if done { break; }
+
+		level_width = level_width.div_ceil(2);
+		level_height = level_height.div_ceil(2);
+	}
+
+	match kind {
+		PyramidKind::DeepZoom => {
+			if let Some(parent) = path.parent() { fs::create_dir_all(parent)?; }
+			fs::create_dir_all(deep_zoom_files(path))?;
+		},
+		PyramidKind::Iiif => fs::create_dir_all(path)?,
+	}
+
+	let target = PyramidTarget { kind, path, tile_size, overlap, quality, width, height, max_level: levels.len() as u32 - 1 };
+
+	for from_y in (0..height).step_by(strip_height as usize) {
+		let strip = draw_strip(from_y, (from_y + strip_height).min(height));
+
+		for row in strip.chunks((width * 3) as usize) {
+			push_row(&target, &mut levels, 0, row.to_vec())?;
+		}
+	}
+
+	write_descriptor(&target, levels.len() as u32)
+}
@@ -0,0 +1,73 @@
+use crate::{ImageUris, ASPECT, IMAGE_DIR, CARD_IMAGE_DIR};
+
+/* width / height of a full card, frame included */
+pub const CARD_ASPECT: f32 = 5.0_f32 / 7.0_f32;
+/* radius of a full card's corners as a fraction of its width */
+pub const CORNER_RADIUS: f32 = 0.05_f32;
+
+/**
+ * which of scryfall's images a library is made of
+ * art crops and full cards have different aspects so they live in different libraries
+ */
+#[derive(Clone, Copy, PartialEq)]
+pub enum ImageKind {
+	ArtCrop,
+	Normal,
+	Large,
+	Png,
+}
+
+pub fn parse_image_kind(name: &str) -> Option<ImageKind> {
+	match name {
+		"art_crop" => Some(ImageKind::ArtCrop),
+		"normal" => Some(ImageKind::Normal),
+		"large" => Some(ImageKind::Large),
+		"png" => Some(ImageKind::Png),
+		_ => None,
+	}
+}
+
+pub fn image_kind_aspect(kind: ImageKind) -> f32 {
+	match kind {
+		ImageKind::ArtCrop => ASPECT,
+		_ => CARD_ASPECT,
+	}
+}
+
+pub fn image_kind_dir(kind: ImageKind) -> &'static str {
+	match kind {
+		ImageKind::ArtCrop => IMAGE_DIR,
+		_ => CARD_IMAGE_DIR,
+	}
+}
+
+pub fn image_kind_uri(kind: ImageKind, image_uris: &ImageUris) -> &str {
+	match kind {
+		ImageKind::ArtCrop => &image_uris.art_crop,
+		ImageKind::Normal => &image_uris.normal,
+		ImageKind::Large => &image_uris.large,
+		ImageKind::Png => &image_uris.png,
+	}
+}
+
+/**
+ * "ffffff" or "#ffffff" to [255, 255, 255]
+ */
+pub fn parse_color(hex: &str) -> Option<[u8; 3]> {
+	let hex = hex.trim_start_matches('#');
+	if hex.len() != 6 { return None; }
+
+	let channel = |index: usize| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok();
+
+	Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/**
+ * whether a point in a card, measured in pixels from its top left, is cut off by a rounded corner
+ */
+pub fn outside_corner(x: f32, y: f32, width: f32, height: f32, radius: f32) -> bool {
+	let corner_x = if x < radius { radius } else if x > width - radius { width - radius } else { return false };
+	let corner_y = if y < radius { radius } else if y > height - radius { height - radius } else { return false };
+
+	(x - corner_x).powi(2) + (y - corner_y).powi(2) > radius.powi(2)
+}
@@ -0,0 +1,168 @@
+use image::{RgbaImage, EncodableLayout};
+use image::imageops::{resize, FilterType};
+
+/**
+ * how card images are resized to the size they're drawn at
+ */
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kernel {
+	Nearest,
+	Bilinear,
+	Lanczos3,
+	/* averages every source pixel under each drawn pixel, only for shrinking */
+	Area,
+}
+
+pub fn parse_kernel(name: &str) -> Option<Kernel> {
+	match name {
+		"nearest" => Some(Kernel::Nearest),
+		"bilinear" | "linear" => Some(Kernel::Bilinear),
+		"lanczos" | "lanczos3" => Some(Kernel::Lanczos3),
+		"area" | "box" => Some(Kernel::Area),
+		_ => None,
+	}
+}
+
+/**
+ * area falls back to bilinear for anything that isn't shrinking both ways
+ */
+pub fn resize_with_kernel(image: &RgbaImage, width: u32, height: u32, kernel: Kernel) -> RgbaImage {
+	match kernel {
+		Kernel::Nearest => resize(image, width, height, FilterType::Nearest),
+		Kernel::Lanczos3 => resize(image, width, height, FilterType::Lanczos3),
+		Kernel::Area if width <= image.width() && height <= image.height() => resize_area(image, width, height),
+		Kernel::Bilinear | Kernel::Area => resize(image, width, height, FilterType::Triangle),
+	}
+}
+
+/**
+ * for each pixel of a row to_length long, the (source pixel, weight) pairs covering it in a row from_length long
+ * source pixels only partly under it count for as much of them as is
+ */
+fn area_weights(from_length: u32, to_length: u32) -> Vec<Vec<(usize, f32)>> {
+	let scale = from_length as f32 / to_length as f32;
+
+	(0..to_length).map(|to| {
+		let start = to as f32 * scale;
+		let end = ((to + 1) as f32 * scale).min(from_length as f32);
+
+		(start.floor() as u32..(end.ceil() as u32).min(from_length)).map(|from| {
+			let covered = end.min((from + 1) as f32) - start.max(from as f32);
+			(from as usize, covered / (end - start))
+		}).collect::<Vec<(usize, f32)>>()
+	}).collect::<Vec<Vec<(usize, f32)>>>()
+}
+
+/**
+ * box filter, rows then columns
+ * colors are weighted by alpha, so transparent pixels don't bleed their color into opaque ones
+ */
+fn resize_area(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+	let (from_width, from_height) = image.dimensions();
+	let bytes = image.as_bytes();
+
+	let column_weights = area_weights(from_width, width);
+	let row_weights = area_weights(from_height, height);
+
+	/* every source row shrunk to the new width, colors premultiplied by alpha */
+	let mut narrow = vec![0f32; width as usize * from_height as usize * 4];
+
+	for y in 0..from_height as usize {
+		for (x, weights) in column_weights.iter().enumerate() {
+			for (from, weight) in weights {
+				let pixel = &bytes[(y * from_width as usize + from) * 4..(y * from_width as usize + from) * 4 + 4];
+				let alpha = pixel[3] as f32 / 255_f32;
+				let narrow_pixel = &mut narrow[(y * width as usize + x) * 4..(y * width as usize + x) * 4 + 4];
+
+				for channel in 0..3 {
+					narrow_pixel[channel] += pixel[channel] as f32 * alpha * weight;
+				}
+				narrow_pixel[3] += pixel[3] as f32 * weight;
+			}
+		}
+	}
+
+	let mut resized = vec![0u8; width as usize * height as usize * 4];
+
+	for (y, weights) in row_weights.iter().enumerate() {
+		for x in 0..width as usize {
+			let mut total = [0f32; 4];
+
+			for (from, weight) in weights {
+				for channel in 0..4 {
+					total[channel] += narrow[(from * width as usize + x) * 4 + channel] * weight;
+				}
+			}
+
+			/* back out of premultiplied */
+			let alpha = total[3] / 255_f32;
+			let resized_pixel = &mut resized[(y * width as usize + x) * 4..(y * width as usize + x) * 4 + 4];
+
+			for channel in 0..3 {
+				resized_pixel[channel] = if alpha > 0_f32 { (total[channel] / alpha).round().min(255_f32) as u8 } else { 0 };
+			}
+			resized_pixel[3] = total[3].round().min(255_f32) as u8;
+		}
+	}
+
+	RgbaImage::from_raw(width, height, resized).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::Rgba;
+
+	#[test]
+	fn weights_sum_to_one() {
+		for (from_length, to_length) in [(10, 3), (7, 7), (100, 33), (5, 4), (1000, 999), (3, 1)].iter() {
+			for weights in area_weights(*from_length, *to_length) {
+				let total = weights.iter().map(|(_, weight)| weight).sum::<f32>();
+				assert!((total - 1_f32).abs() < 0.0001_f32, "{} to {} sums to {}", from_length, to_length, total);
+			}
+		}
+	}
+
+	#[test]
+	fn weights_cover_every_source_pixel() {
+		let weights = area_weights(10, 3);
+
+		assert_eq!(weights[0].first().unwrap().0, 0);
+		assert_eq!(weights[2].last().unwrap().0, 9);
+		for pair in weights.windows(2) {
+			assert!(pair[1].first().unwrap().0 <= pair[0].last().unwrap().0 + 1);
+		}
+	}
+
+	#[test]
+	fn integer_shrink_averages() {
+		/* each 2 x 2 block has one pixel of every value, so each comes out as the block's exact mean */
+		let image = RgbaImage::from_fn(4, 2, |x, y| {
+			let value = [10, 20, 30, 40][(y * 2 + x % 2) as usize] + (x / 2) as u8 * 100;
+			Rgba([value, 255 - value, value / 2, 255])
+		});
+
+		let resized = resize_area(&image, 2, 1);
+
+		assert_eq!(*resized.get_pixel(0, 0), Rgba([25, 230, 13, 255]));
+		assert_eq!(*resized.get_pixel(1, 0), Rgba([125, 130, 63, 255]));
+	}
+
+	#[test]
+	fn transparent_pixels_dont_bleed() {
+		let mut image = RgbaImage::from_pixel(2, 2, Rgba([200, 100, 50, 255]));
+		image.put_pixel(1, 1, Rgba([0, 0, 0, 0]));
+
+		let resized = resize_area(&image, 1, 1);
+
+		assert_eq!(*resized.get_pixel(0, 0), Rgba([200, 100, 50, 191]));
+	}
+
+	#[test]
+	fn area_only_shrinks() {
+		let image = RgbaImage::from_pixel(2, 2, Rgba([1, 2, 3, 255]));
+
+		assert_eq!(resize_with_kernel(&image, 4, 4, Kernel::Area).dimensions(), (4, 4));
+		assert_eq!(resize_with_kernel(&image, 1, 4, Kernel::Area).dimensions(), (1, 4));
+	}
+}
@@ -0,0 +1,334 @@
+use image::{RgbImage, GrayImage, EncodableLayout};
+use crate::{CardGrid, Tile};
+use crate::alpha::ALPHA_THRESHOLD;
+
+/**
+ * how cells are packed into the grid
+ * every layout is measured in cells, one cell is one card wide and one card tall
+ */
+#[derive(Clone, Copy, PartialEq)]
+pub enum Layout {
+	Square,
+	/* every other row shifted over by half a card, like a brick wall */
+	Brick,
+	/* pointy topped hexagons cropped out of each card, rows overlap by a quarter */
+	Hexagon,
+}
+
+pub fn parse_layout(name: &str) -> Option<Layout> {
+	match name {
+		"square" => Some(Layout::Square),
+		"brick" => Some(Layout::Brick),
+		"hex" | "hexagon" => Some(Layout::Hexagon),
+		_ => None,
+	}
+}
+
+pub fn layout_name(layout: Layout) -> &'static str {
+	match layout {
+		Layout::Square => "square",
+		Layout::Brick => "brick",
+		Layout::Hexagon => "hex",
+	}
+}
+
+/**
+ * a single sample for a tile
+ * mask marks which sample pixels fall inside the tile's polygon and the image, and aren't transparent
+ */
+pub struct TileSample {
+	pub image: RgbImage,
+	pub mask: Vec<bool>,
+}
+
+pub fn square_tile(x: u32, y: u32, span: u32) -> Tile {
+	rect_tile(x as f32, y as f32, span as f32, span as f32)
+}
+
+fn rect_tile(x: f32, y: f32, width: f32, height: f32) -> Tile {
+	Tile {
+		polygon: vec![
+			(x, y),
+			(x + width, y),
+			(x + width, y + height),
+			(x, y + height),
+		]
+	}
+}
+
+fn hexagon_tile(x: f32, y: f32) -> Tile {
+	Tile {
+		polygon: vec![
+			(x + 0.5_f32, y),
+			(x + 1.0_f32, y + 0.25_f32),
+			(x + 1.0_f32, y + 0.75_f32),
+			(x + 0.5_f32, y + 1.0_f32),
+			(x, y + 0.75_f32),
+			(x, y + 0.25_f32),
+		]
+	}
+}
+
+/**
+ * (min x, min y, max x, max y) of a tile in cells
+ */
+pub fn tile_bounds(tile: &Tile) -> (f32, f32, f32, f32) {
+	tile.polygon.iter().fold(
+		(f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+		|(min_x, min_y, max_x, max_y), &(x, y)| (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+	)
+}
+
+pub fn tile_center(tile: &Tile) -> (f32, f32) {
+	let (min_x, min_y, max_x, max_y) = tile_bounds(tile);
+
+	((min_x + max_x) / 2.0_f32, (min_y + max_y) / 2.0_f32)
+}
+
+/**
+ * crossing number test
+ * shared edges between neighboring tiles only count for one of them
+ */
+pub fn tile_contains(tile: &Tile, x: f32, y: f32) -> bool {
+	let polygon = &tile.polygon;
+	let mut inside = false;
+	let mut last = polygon.len() - 1;
+
+	for current in 0..polygon.len() {
+		let (x0, y0) = polygon[current];
+		let (x1, y1) = polygon[last];
+
+		if (y0 > y) != (y1 > y) && x < (x1 - x0) * (y - y0) / (y1 - y0) + x0 {
+			inside = !inside;
+		}
+
+		last = current;
+	}
+
+	inside
+}
+
+/**
+ * how far a point is from the nearest edge of a tile, both scaled by cell_width and cell_height first
+ * used in pixels, so that gaps between tiles come out the same width whichever way the edges run
+ */
+pub fn tile_edge_distance(tile: &Tile, x: f32, y: f32, cell_width: f32, cell_height: f32) -> f32 {
+	let polygon = &tile.polygon;
+	let mut last = polygon.len() - 1;
+	let mut distance = f32::MAX;
+
+	for current in 0..polygon.len() {
+		let (x0, y0) = (polygon[current].0 * cell_width, polygon[current].1 * cell_height);
+		let (x1, y1) = (polygon[last].0 * cell_width, polygon[last].1 * cell_height);
+
+		/* closest point on the edge, as a fraction of the way from 0 to 1 */
+		let (edge_x, edge_y) = (x1 - x0, y1 - y0);
+		let along = (((x - x0) * edge_x + (y - y0) * edge_y) / (edge_x * edge_x + edge_y * edge_y).max(f32::EPSILON)).clamp(0.0_f32, 1.0_f32);
+
+		distance = distance.min(((x - x0 - edge_x * along).powi(2) + (y - y0 - edge_y * along).powi(2)).sqrt());
+		last = current;
+	}
+
+	distance
+}
+
+/**
+ * all the tiles of a layout covering a cards_wide x cards_tall area
+ * tiles hanging off the edges are kept, they will be cut off when drawn
+ */
+pub fn create_layout_tiles(layout: Layout, cards_wide: u32, cards_tall: u32) -> Vec<Tile> {
+	let mut tiles = Vec::with_capacity((cards_wide * cards_tall) as usize);
+
+	let width = cards_wide as f32;
+	let height = cards_tall as f32;
+
+	let mut keep_tile = |tile: Tile| {
+		let (min_x, min_y, max_x, max_y) = tile_bounds(&tile);
+
+		if max_x > 0.0_f32 && max_y > 0.0_f32 && min_x < width && min_y < height {
+			tiles.push(tile);
+		}
+	};
+
+	match layout {
+		Layout::Square => {
+			for y in 0..cards_tall {
+				for x in 0..cards_wide {
+					keep_tile(square_tile(x, y, 1));
+				}
+			}
+		},
+		Layout::Brick => {
+			for y in 0..cards_tall {
+				let offset = if y % 2 == 1 { -0.5_f32 } else { 0.0_f32 };
+
+				for x in 0..=cards_wide {
+					keep_tile(rect_tile(x as f32 + offset, y as f32, 1.0_f32, 1.0_f32));
+				}
+			}
+		},
+		Layout::Hexagon => {
+			/* start a row early so the top points have something to fill between them */
+			let mut row = -1_i32;
+
+			while row as f32 * 0.75_f32 < height {
+				let offset = if row.rem_euclid(2) == 1 { -0.5_f32 } else { 0.0_f32 };
+
+				for x in 0..=cards_wide {
+					keep_tile(hexagon_tile(x as f32 + offset, row as f32 * 0.75_f32));
+				}
+
+				row += 1;
+			}
+		},
+	}
+
+	tiles
+}
+
+/**
+ * one sample_width x sample_height sample per tile, taken from the tile's bounding box
+ * each sample pixel is the average of the sample image pixels that fall in it
+ */
+pub fn create_tile_samples(
+	sample_image: &RgbImage,
+	alpha_image: Option<&GrayImage>,
+	card_grid: &CardGrid,
+	sample_width: u32,
+	sample_height: u32,
+) -> Vec<TileSample> {
+	let bytes = sample_image.as_bytes();
+	let image_width = sample_image.width();
+	let image_height = sample_image.height();
+
+	/* sample image pixels per cell */
+	let scale_x = image_width as f32 / card_grid.cards_wide as f32;
+	let scale_y = image_height as f32 / card_grid.cards_tall as f32;
+
+	card_grid.tiles.iter().map(|tile| {
+		let (min_x, min_y, max_x, max_y) = tile_bounds(tile);
+		let step_x = (max_x - min_x) / sample_width as f32;
+		let step_y = (max_y - min_y) / sample_height as f32;
+
+		let mut sample_bytes = Vec::with_capacity(sample_width as usize * sample_height as usize * 3);
+		let mut mask = Vec::with_capacity(sample_width as usize * sample_height as usize);
+
+		for j in 0..sample_height {
+			let cell_y = min_y + (j as f32 + 0.5_f32) * step_y;
+
+			let from_y = ((min_y + j as f32 * step_y) * scale_y).floor().max(0.0_f32) as u32;
+			let to_y = (((min_y + (j + 1) as f32 * step_y) * scale_y).ceil().max(0.0_f32) as u32).min(image_height);
+
+			for i in 0..sample_width {
+				let cell_x = min_x + (i as f32 + 0.5_f32) * step_x;
+
+				let from_x = ((min_x + i as f32 * step_x) * scale_x).floor().max(0.0_f32) as u32;
+				let to_x = (((min_x + (i + 1) as f32 * step_x) * scale_x).ceil().max(0.0_f32) as u32).min(image_width);
+
+				/* colors are weighted by their alpha so transparent pixels don't darken the ones around them */
+				let mut total = [0_u32; 3];
+				let mut weight = 0_u32;
+				let mut count = 0_u32;
+
+				for y in from_y..to_y {
+					for x in from_x..to_x {
+						let index = ((y * image_width + x) * 3) as usize;
+						let alpha = alpha_image.map_or(255_u32, |alpha_image| alpha_image.get_pixel(x, y)[0] as u32);
+						total[0] += bytes[index] as u32 * alpha;
+						total[1] += bytes[index + 1] as u32 * alpha;
+						total[2] += bytes[index + 2] as u32 * alpha;
+						weight += alpha;
+						count += 1;
+					}
+				}
+
+				let in_image = count > 0 && cell_x >= 0.0_f32 && cell_y >= 0.0_f32
+					&& cell_x < card_grid.cards_wide as f32 && cell_y < card_grid.cards_tall as f32;
+				let opaque = count > 0 && weight as f32 / count as f32 >= ALPHA_THRESHOLD as f32;
+
+				mask.push(in_image && opaque && tile_contains(tile, cell_x, cell_y));
+
+				for channel in total.iter() {
+					sample_bytes.push(if weight > 0 { (*channel as f32 / weight as f32).round() as u8 } else { 0 });
+				}
+			}
+		}
+
+		TileSample {
+			image: RgbImage::from_raw(sample_width, sample_height, sample_bytes).unwrap(),
+			mask,
+		}
+	}).collect::<Vec<TileSample>>()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn square_contains() {
+		let tile = square_tile(1, 2, 2);
+
+		assert!(tile_contains(&tile, 2_f32, 3_f32));
+		assert!(tile_contains(&tile, 1.01_f32, 3.99_f32));
+		assert!(!tile_contains(&tile, 0.99_f32, 3_f32));
+		assert!(!tile_contains(&tile, 2_f32, 4.01_f32));
+	}
+
+	#[test]
+	fn hexagon_corners_are_outside() {
+		let tile = hexagon_tile(0_f32, 0_f32);
+
+		assert!(tile_contains(&tile, 0.5_f32, 0.5_f32));
+		assert!(!tile_contains(&tile, 0.05_f32, 0.05_f32));
+		assert!(!tile_contains(&tile, 0.95_f32, 0.95_f32));
+	}
+
+	#[test]
+	fn shared_edges_count_once() {
+		let (left, right) = (square_tile(0, 0, 1), square_tile(1, 0, 1));
+
+		for y in [0.25_f32, 0.5_f32, 0.75_f32].iter() {
+			assert!(tile_contains(&left, 1_f32, *y) != tile_contains(&right, 1_f32, *y));
+		}
+	}
+
+	#[test]
+	fn edge_distance_in_pixels() {
+		let tile = square_tile(0, 0, 1);
+
+		/* a 100 x 50 pixel cell, the short way across is the nearest edge */
+		assert!((tile_edge_distance(&tile, 50_f32, 25_f32, 100_f32, 50_f32) - 25_f32).abs() < 0.001_f32);
+		assert!((tile_edge_distance(&tile, 10_f32, 25_f32, 100_f32, 50_f32) - 10_f32).abs() < 0.001_f32);
+		assert!(tile_edge_distance(&tile, 0_f32, 30_f32, 100_f32, 50_f32).abs() < 0.001_f32);
+	}
+
+	#[test]
+	fn edge_distance_to_slanted_edges() {
+		let tile = hexagon_tile(0_f32, 0_f32);
+
+		/* the top vertex is on two edges, the middle is closer to the slanted edges than the sides */
+		assert!(tile_edge_distance(&tile, 0.5_f32, 0_f32, 1_f32, 1_f32).abs() < 0.001_f32);
+		assert!((tile_edge_distance(&tile, 0.5_f32, 0.5_f32, 1_f32, 1_f32) - 0.25_f32 / 0.3125_f32.sqrt()).abs() < 0.001_f32);
+
+		/* halfway along the top right edge, and a step straight out from it */
+		let (edge_x, edge_y) = (0.5_f32, 0.25_f32);
+		let length = (edge_x * edge_x + edge_y * edge_y).sqrt();
+		let (out_x, out_y) = (edge_y / length * 0.1_f32, -edge_x / length * 0.1_f32);
+		assert!((tile_edge_distance(&tile, 0.75_f32 + out_x, 0.125_f32 + out_y, 1_f32, 1_f32) - 0.1_f32).abs() < 0.001_f32);
+	}
+
+	#[test]
+	fn layouts_cover_every_point_once() {
+		for layout in [Layout::Square, Layout::Brick, Layout::Hexagon].iter() {
+			let tiles = create_layout_tiles(*layout, 4, 3);
+
+			for i in 0..40 {
+				for j in 0..30 {
+					let (x, y) = (i as f32 * 0.1_f32 + 0.013_f32, j as f32 * 0.1_f32 + 0.017_f32);
+					assert_eq!(tiles.iter().filter(|tile| tile_contains(tile, x, y)).count(), 1, "{} at {}, {}", layout_name(*layout), x, y);
+				}
+			}
+		}
+	}
+}
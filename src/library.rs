@@ -0,0 +1,242 @@
+use image::{DynamicImage, ImageFormat};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::crop_card;
+use crate::cache::{CACHE_FILE, hash_bytes};
+use crate::crop::{CropStrategy, crop_with_strategy};
+use crate::metadata::METADATA_FILE;
+use crate::collection::COLLECTION_FILE;
+
+/* only files with these extensions are treated as cards */
+const IMAGE_EXTENSIONS: [&str; 8] = ["png", "jpg", "jpeg", "webp", "bmp", "gif", "tif", "tiff"];
+/* broken files are moved in here by library check, and it's never loaded from */
+const QUARANTINE_DIR: &str = "quarantine";
+
+/**
+ * a card in the library
+ * the image itself stays on disk until something actually needs its pixels
+ */
+#[derive(Clone)]
+pub struct LibraryCard {
+	pub path: PathBuf,
+	/* crop to the card aspect when loaded */
+	pub crop: bool,
+}
+
+/**
+ * what was left out while loading a library
+ */
+pub struct LoadSummary {
+	pub skipped: Vec<PathBuf>,
+	pub unreadable: Vec<PathBuf>,
+}
+
+/**
+ * the sample cache, any half written copy of it, and card metadata live alongside the cards
+ */
+fn is_library_file(name: &str) -> bool {
+	name.starts_with(CACHE_FILE) || name == METADATA_FILE || name == COLLECTION_FILE
+}
+
+fn is_image_file(path: &Path) -> bool {
+	path.extension()
+		.map(|extension| extension.to_string_lossy().to_lowercase())
+		.is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.as_str()))
+}
+
+/**
+ * every image file in a folder, and everything else that was found there
+ * the quarantine folder is always left out
+ */
+fn find_image_files(dir_path: &Path, recursive: bool, images: &mut Vec<PathBuf>, skipped: &mut Vec<PathBuf>) {
+	let entries = match fs::read_dir(dir_path) {
+		Ok(entries) => entries,
+		Err(_) => {
+			skipped.push(dir_path.to_path_buf());
+			return;
+		}
+	};
+
+	for entry in entries.filter_map(|entry| entry.ok()) {
+		let path = entry.path();
+
+		if path.is_dir() {
+			if path.file_name().is_some_and(|name| name == QUARANTINE_DIR) { continue; }
+
+			if recursive {
+				find_image_files(&path, recursive, images, skipped);
+			} else {
+				skipped.push(path);
+			}
+
+		} else if is_image_file(&path) {
+			images.push(path);
+
+		} else if !path.file_name().is_some_and(|name| is_library_file(&name.to_string_lossy())) {
+			skipped.push(path);
+		}
+	}
+}
+
+/**
+ * only reads enough of each file to know it's an image,
+ * corrupt pixel data further in is caught when the card is first sampled
+ */
+pub fn load_existing_images(dir_path: &str, cards: &mut Vec<LibraryCard>, crop: bool, recursive: bool) -> LoadSummary {
+	let mut paths = Vec::new();
+	let mut skipped = Vec::new();
+	let mut unreadable = Vec::new();
+
+	find_image_files(Path::new(dir_path), recursive, &mut paths, &mut skipped);
+	paths.sort();
+
+	for path in paths {
+		if image::image_dimensions(&path).is_ok() {
+			cards.push(LibraryCard { path, crop });
+		} else {
+			unreadable.push(path);
+		}
+	}
+
+	LoadSummary { skipped, unreadable }
+}
+
+fn decodes(path: &Path) -> bool {
+	fs::read(path).ok().and_then(|bytes| image::load_from_memory(&bytes).ok()).is_some()
+}
+
+pub fn print_load_summary(summary: &LoadSummary) {
+	if !summary.skipped.is_empty() {
+		println!("Skipped {} files that aren't images", summary.skipped.len());
+	}
+
+	if !summary.unreadable.is_empty() {
+		println!("Skipped {} unreadable images, run library check to find out more:", summary.unreadable.len());
+
+		for path in &summary.unreadable {
+			println!("    {}", path.display());
+		}
+	}
+}
+
+/**
+ * decodes a card's full resolution image from disk, none if it's broken
+ */
+pub fn try_load_card_image(card: &LibraryCard, card_aspect: f32) -> Option<DynamicImage> {
+	let original_image = fs::read(&card.path).ok()
+		.and_then(|bytes| image::load_from_memory(&bytes).ok())?;
+
+	Some(if card.crop { crop_card(original_image, card_aspect) } else { original_image })
+}
+
+/**
+ * for cards that already decoded fine when they were sampled
+ */
+pub fn load_card_image(card: &LibraryCard, card_aspect: f32) -> DynamicImage {
+	try_load_card_image(card, card_aspect)
+		.unwrap_or_else(|| panic!("Couldn't decode {}, run library check!", card.path.display()))
+}
+
+/**
+ * moves broken images into the library's quarantine folder, where they're never loaded from
+ * they keep their path inside the library, so files of the same name in different subfolders don't overwrite each other
+ */
+fn quarantine_images(dir_path: &str, paths: &[PathBuf]) -> std::io::Result<()> {
+	let quarantine_dir = Path::new(dir_path).join(QUARANTINE_DIR);
+
+	for path in paths {
+		let relative_path = path.strip_prefix(dir_path).unwrap_or_else(|_| Path::new(path.file_name().unwrap()));
+		let quarantine_path = quarantine_dir.join(relative_path);
+
+		fs::create_dir_all(quarantine_path.parent().unwrap())?;
+		fs::rename(path, quarantine_path)?;
+	}
+
+	println!("Moved {} broken images to {}", paths.len(), quarantine_dir.display());
+
+	Ok(())
+}
+
+/**
+ * fully decodes every image in a library and lists the ones that fail
+ * with quarantine they're moved into the library's quarantine folder
+ */
+pub fn check_library(dir_path: &str, recursive: bool, quarantine: bool) -> std::io::Result<()> {
+	let mut paths = Vec::new();
+	let mut skipped = Vec::new();
+
+	find_image_files(Path::new(dir_path), recursive, &mut paths, &mut skipped);
+	paths.sort();
+
+	let broken = paths.iter()
+		.filter(|path| !decodes(path))
+		.cloned()
+		.collect::<Vec<PathBuf>>();
+
+	println!("Checked {} images, {} broken, {} other files", paths.len(), broken.len(), skipped.len());
+
+	for path in &skipped {
+		println!("    not an image: {}", path.display());
+	}
+
+	for path in &broken {
+		println!("    broken: {}", path.display());
+	}
+
+	if quarantine && !broken.is_empty() {
+		quarantine_images(dir_path, &broken)?;
+	}
+
+	Ok(())
+}
+
+/**
+ * copies every image in a folder into the library, brought to the card aspect with strategy
+ * files are named after the original and a hash of its contents,
+ * so importing the same folder twice doesn't create duplicates
+ */
+pub fn import_images(source_dir: &str, library_dir: &str, card_aspect: f32, strategy: CropStrategy, recursive: bool) -> std::io::Result<()> {
+	let mut paths = Vec::new();
+	let mut skipped = Vec::new();
+	let mut unreadable = Vec::new();
+	let mut imported = 0u32;
+	let mut existing = 0u32;
+
+	find_image_files(Path::new(source_dir), recursive, &mut paths, &mut skipped);
+	paths.sort();
+
+	for path in paths {
+		let bytes = match fs::read(&path) {
+			Ok(bytes) => bytes,
+			Err(_) => {
+				unreadable.push(path);
+				continue;
+			},
+		};
+
+		let save_path = Path::new(library_dir).join(format!(
+			"{}-{:016x}.png",
+			path.file_stem().unwrap().to_string_lossy(),
+			hash_bytes(&bytes),
+		));
+
+		if save_path.exists() {
+			existing += 1;
+			continue;
+		}
+
+		match image::load_from_memory(&bytes) {
+			Ok(original_image) => {
+				crop_with_strategy(original_image, card_aspect, strategy).save_with_format(&save_path, ImageFormat::Png)
+					.map_err(|err| std::io::Error::other(err.to_string()))?;
+				imported += 1;
+			},
+			Err(_) => unreadable.push(path),
+		}
+	}
+
+	println!("Imported {} images, {} were already in the library", imported, existing);
+	print_load_summary(&LoadSummary { skipped, unreadable });
+
+	Ok(())
+}
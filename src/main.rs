@@ -1,5 +1,25 @@
 mod new_sample;
 mod preprocess;
+mod quadtree;
+mod layout;
+mod image_kind;
+mod transform;
+mod window;
+mod pyramid;
+mod library;
+mod cache;
+mod crop;
+mod output;
+mod print;
+mod render;
+mod deepzoom;
+mod metadata;
+mod viewer;
+mod manifest;
+mod plan;
+mod collection;
+mod kernel;
+mod alpha;
 
 use reqwest::{Client};
 use serde::Deserialize;
@@ -7,94 +27,303 @@ use image::{DynamicImage, GenericImageView, ImageFormat, EncodableLayout, RgbIma
 use image::imageops::{FilterType};
 use uuid::Uuid;
 use std::fs::create_dir;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::convert::From;
 use std::{fs, env};
 use std::io::ErrorKind;
+use std::collections::HashMap;
 
 use crate::new_sample::{populate_grid_new};
 use crate::preprocess::add_duplicates;
+use crate::quadtree::subdivide_grid;
+use crate::image_kind::{ImageKind, parse_image_kind, parse_color, image_kind_aspect, image_kind_dir, image_kind_uri, CORNER_RADIUS};
+use crate::transform::{Augment, parse_augment, augment_transforms, apply_transform};
+use crate::pyramid::{Pyramid, coarse_candidates};
+use crate::window::{Variant, CENTER_VARIANT, window_offsets, create_variants, crop_window};
+use crate::layout::{Layout, TileSample, parse_layout, create_layout_tiles, create_tile_samples};
+use crate::library::{LibraryCard, LoadSummary, load_existing_images, load_card_image, try_load_card_image, print_load_summary, check_library, import_images};
+use crate::crop::{CropStrategy, parse_crop_strategy, library_image};
+use crate::output::{OutputOptions, parse_output_format, output_target, keeps_alpha, save_output};
+use crate::print::{parse_print_size, output_image_width, warn_upscaled};
+use crate::render::{TileStyle, create_renderer, draw_strip, STRIP_HEIGHT};
+use crate::kernel::{Kernel, parse_kernel};
+use crate::alpha::{Shape, create_alpha_image, apply_mask, create_shape, has_opaque_part, count_opaque_tiles, remove_transparent_tiles};
+use crate::metadata::{CardMetadata, load_metadata, save_metadata};
+use crate::viewer::write_viewer;
+use crate::manifest::{create_manifest, save_manifest, load_manifest, manifest_grid};
+use crate::plan::write_plan;
+use crate::collection::{CollectionEntry, parse_collection, is_set_code, find_library_card, load_collection, save_collection, restrict_to_collection, expand_owned_copies};
+use crate::cache::{SampleCache, load_sample_cache, save_sample_cache, validate_cache, has_sample, lookup_sample, store_sample};
 
 const ASPECT: f32 = 16.0_f32 / 9.0_f32;
 //const IMAGE_DIR: &str = "./clowdy anki pictures/";
 const IMAGE_DIR: &str = "./ankiImages/";
+const CARD_IMAGE_DIR: &str = "./ankiCards/";
 const BASE_IMAGE_DIR: &str = "./test/BAAM.png";
 const CARDS_WIDE: u32 = 80;
 const IMAGE_WIDTH: u32 = 2000;
+/* dots per inch of a --print without --dpi */
+const PRINT_DPI: u32 = 300;
+/* where the result goes without --output, its extension picks the format */
+const OUTPUT_PATH: &str = "./test/sampled.png";
+/* jpeg, lossy webp and avif quality without --quality */
+const OUTPUT_QUALITY: u8 = 90;
+/* samples are this many pixels along the card's short side */
 const SAMPLE_SIZE: u32 = 9;
+/* short sides of the samples used by --pyramid, and how many cards per tile get the fine pass */
+const PYRAMID_COARSE_SIZE: u32 = 3;
+const PYRAMID_FINE_SIZE: u32 = 18;
+const PYRAMID_CANDIDATES: usize = 16;
+/* detail under which blocks are merged when --max-span is given without --detail */
+const DETAIL_THRESHOLD: f32 = 24.0;
+const LAYOUT: Layout = Layout::Square;
+/* grout and border colors without --grout-color or --border-color, grout also shows wherever no card is drawn */
+const GROUT_COLOR: [u8; 3] = [0, 0, 0];
+const BORDER_COLOR: [u8; 3] = [0, 0, 0];
+/* how cards are resized to their tiles without --kernel */
+const KERNEL: Kernel = Kernel::Lanczos3;
 const NORMAL_LAYOUT: &str = "normal";
+/* scryfall asks for 50 to 100 milliseconds between requests */
+const SCRYFALL_DELAY_MS: u64 = 100;
 
 #[tokio::main]
 async fn main() {
 	/* see what we're gonna do for this run */
 	let args: Vec<String> = env::args().collect();
 
+	let layout = flag_value(&args, "--layout").map(|name| parse_layout(name).expect("Unknown layout!")).unwrap_or(LAYOUT);
+	let image_kind = flag_value(&args, "--image").map(|name| parse_image_kind(name).expect("Unknown image kind!")).unwrap_or(ImageKind::ArtCrop);
+	let augment = flag_value(&args, "--augment").map(|name| parse_augment(name).expect("Unknown augment!")).unwrap_or(Augment::None);
+	let num_windows = flag_value(&args, "--windows").map(|count| count.parse::<u32>().expect("Windows must be a number!")).unwrap_or(1);
+	let corner_color = flag_value(&args, "--corners").map(|color| parse_color(color).expect("Corner color must be hex like ffffff!"));
+	/* transparent parts of the base image are left empty in the output, or filled with the background */
+	let alpha = args.iter().any(|arg| arg == "--alpha");
+	let background = flag_value(&args, "--background").map(|color| parse_color(color).expect("Background color must be hex like ffffff!"));
+	let kernel = flag_value(&args, "--kernel").map(|name| parse_kernel(name).expect("Unknown kernel!")).unwrap_or(KERNEL);
+	/* --corners fills in behind full cards' corners, which is the grout, alpha output leaves it see through unless given */
+	let grout_color = flag_value(&args, "--grout-color").map(|color| parse_color(color).expect("Grout color must be hex like ffffff!"))
+		.or(corner_color)
+		.or(background)
+		.or(if alpha { None } else { Some(GROUT_COLOR) });
+	let tile_style = TileStyle {
+		grout: flag_value(&args, "--grout").map(|width| width.parse::<f32>().expect("Grout must be a number of pixels!")).unwrap_or(0_f32),
+		grout_color,
+		border: flag_value(&args, "--border").map(|width| width.parse::<f32>().expect("Border must be a number of pixels!")).unwrap_or(0_f32),
+		border_color: flag_value(&args, "--border-color").map(|color| parse_color(color).expect("Border color must be hex like ffffff!")).unwrap_or(BORDER_COLOR),
+		corner_radius: flag_value(&args, "--radius").map(|radius| radius.parse::<f32>().expect("Radius must be a fraction of the card width!"))
+			.unwrap_or(if corner_color.is_some() { CORNER_RADIUS } else { 0_f32 }),
+		background: if alpha { background } else { background.or(grout_color) },
+	};
+	let recursive = args.iter().any(|arg| arg == "--recursive");
+	let print_size = flag_value(&args, "--print").map(|size| parse_print_size(size).expect("Print size must be like 24x36 or 60x90cm!"));
+	let dpi = flag_value(&args, "--dpi").map(|dpi| dpi.parse::<u32>().expect("Dpi must be a number!"));
+	let output_format = flag_value(&args, "--format").map(|name| parse_output_format(name).expect("Unknown output format!"));
+	let (output_path, output_format) = output_target(flag_value(&args, "--output"), output_format, OUTPUT_PATH);
+	if alpha && !keeps_alpha(output_format) { panic!("Alpha output needs png, webp or tiff!"); }
+	let output_options = OutputOptions {
+		format: output_format,
+		quality: flag_value(&args, "--quality").map(|quality| quality.parse::<u8>().expect("Quality must be a number!")).unwrap_or(OUTPUT_QUALITY).clamp(1, 100),
+		lossless: args.iter().any(|arg| arg == "--lossless"),
+		alpha,
+		/* only written into the file when printing was asked for */
+		dpi: if print_size.is_some() { Some(dpi.unwrap_or(PRINT_DPI)) } else { dpi },
+	};
+	let dpi = dpi.unwrap_or(PRINT_DPI);
+	let viewer = args.iter().any(|arg| arg == "--viewer");
+	/* json or csv, by extension */
+	let manifest_path = flag_value(&args, "--manifest").map(PathBuf::from);
+	let plan_path = flag_value(&args, "--plan").map(PathBuf::from);
+	/* only use cards from the imported collection, each as many times as it's owned */
+	let owned_only = args.iter().any(|arg| arg == "--collection");
+	/* only the mask's shape gets cards, without one the base image's own alpha is the shape */
+	let mask_path = flag_value(&args, "--mask");
+	/* largest merged tile in cells, a power of 2, merging is off unless asked for */
+	let max_span = flag_value(&args, "--max-span").map(|span| span.parse::<u32>().ok().filter(|span| span.is_power_of_two()).expect("Max span must be a power of 2!")).unwrap_or(1);
+	let detail_threshold = flag_value(&args, "--detail").map(|detail| detail.parse::<f32>().expect("Detail must be a number!")).unwrap_or(DETAIL_THRESHOLD);
+	let crop_strategy = flag_value(&args, "--crop").map(|name| parse_crop_strategy(name).expect("Unknown crop strategy!")).unwrap_or(CropStrategy::Center);
+
+	let image_dir = image_kind_dir(image_kind);
+	let aspect = image_kind_aspect(image_kind);
+	let (sample_width, sample_height) = sample_dimensions(SAMPLE_SIZE, aspect);
+
+	let pyramid = if args.iter().any(|arg| arg == "--pyramid") {
+		let (fine_width, fine_height) = sample_dimensions(PYRAMID_FINE_SIZE, aspect);
+		Some(Pyramid { fine_width, fine_height, candidates: PYRAMID_CANDIDATES })
+	} else {
+		None
+	};
+
+	/* with a pyramid the regular samples are the coarse ones */
+	let (match_width, match_height) = if pyramid.is_some() { sample_dimensions(PYRAMID_COARSE_SIZE, aspect) } else { (sample_width, sample_height) };
+	let variants = create_variants(&window_offsets(num_windows), &augment_transforms(augment));
+	/* every size cards get sampled at this run */
+	let sample_sizes = [Some((match_width, match_height)), pyramid.map(|pyramid| (pyramid.fine_width, pyramid.fine_height))]
+		.iter().flatten().copied().collect::<Vec<(u32, u32)>>();
+
 	if args.len() == 1 {
 		println!("No arguments provided!");
 
 	} else if args[1] == "pull" {
-		let mut card_images: Vec<DynamicImage> = Vec::new();
-		save_num_cards(IMAGE_DIR, &mut card_images, ASPECT, 100).await.unwrap();
+		let mut card_images: Vec<LibraryCard> = Vec::new();
+		save_num_cards(image_dir, &mut card_images, image_kind, crop_strategy, 100).await.unwrap();
 
 	} else if args[1] == "resample" {
-		setup_dir(IMAGE_DIR).unwrap();
-
-		let mut card_images: Vec<DynamicImage> = Vec::with_capacity(32);
+		setup_dir(image_dir).unwrap();
 
-		load_existing_images(IMAGE_DIR, &mut card_images, ASPECT, false);
+		let mut card_images: Vec<LibraryCard> = Vec::with_capacity(32);
 
-		let mut used_cards = vec![false; card_images.len()];
+		let mut load_summary = load_existing_images(image_dir, &mut card_images, false, recursive);
+		let mut sample_cache = open_sample_cache(image_dir, &mut card_images, &mut load_summary, &sample_sizes, aspect, &variants);
+		print_load_summary(&load_summary);
 
 		println!("Loaded {} images!", card_images.len());
 
-		let base_image = image::load_from_memory(fs::read(BASE_IMAGE_DIR).unwrap().as_slice()).unwrap();
+		let usage_caps = if owned_only { Some(restrict_to_owned(image_dir, &mut card_images)) } else { None };
+
+		let base_image = load_base_image(mask_path);
 
 		println!("Loaded base image!");
 
-		let mut card_grid = create_grid(CARDS_WIDE, ASPECT, base_image.width(), base_image.height());
+		let mut card_grid = create_grid(CARDS_WIDE, aspect, base_image.width(), base_image.height(), layout);
+		subdivide_grid(&base_image, &mut card_grid, sample_width, sample_height, max_span, detail_threshold);
+		/* at most every cell is opaque, so that's enough resolution whatever the shape */
+		let shape = create_shape(&base_image, card_grid.cards_wide * card_grid.cards_tall, sample_width, sample_height);
+		remove_transparent_tiles(shape.as_ref(), &mut card_grid);
 
-		populate_grid(&base_image, &card_images, &mut used_cards, &mut card_grid, SAMPLE_SIZE);
+		populate_grid(&base_image, &card_images, &mut sample_cache, &mut card_grid, match_width, match_height, aspect, &variants, pyramid, usage_caps.as_deref());
+		save_sample_cache(&mut sample_cache).unwrap();
 
 		println!("Found cards to sample!");
 
-		let (card_draw_images, card_draw_indices) = create_draw_cards(&card_images, &used_cards, CARDS_WIDE, IMAGE_WIDTH, ASPECT);
+		let image_width = output_image_width(print_size, dpi, &card_grid, aspect, IMAGE_WIDTH);
+		warn_upscaled(&card_grid, &card_images, aspect, image_width);
 
-		let output_image = draw_cards(&card_grid, card_draw_images, card_draw_indices, ASPECT, IMAGE_WIDTH);
+		let mut renderer = create_renderer(&card_grid, &card_images, aspect, image_width, tile_style, kernel, alpha);
+		let image_height = renderer.image_height;
+
+		save_output(&output_path, output_options, image_width, image_height, STRIP_HEIGHT, &mut |from_y, to_y| draw_strip(&mut renderer, from_y, to_y)).unwrap();
 
 		println!("Drew sampled image!");
 
-		output_image.save_with_format("./test/sampled.png", ImageFormat::Png).unwrap();
+		save_placement(manifest_path.as_deref(), plan_path.as_deref(), &card_grid, &card_images, image_dir, aspect, image_width);
+
+		if viewer {
+			write_viewer(&output_path.with_extension("html"), &output_path, output_format, &card_grid, &card_images, &load_metadata(image_dir)).unwrap();
+		}
 
 	} else if args[1] == "new" {
-		setup_dir(IMAGE_DIR).unwrap();
-		let mut card_images: Vec<DynamicImage> = Vec::with_capacity(32);
+		setup_dir(image_dir).unwrap();
+		let mut card_images: Vec<LibraryCard> = Vec::with_capacity(32);
 
 		println!("Loading card images...");
-		load_existing_images(IMAGE_DIR, &mut card_images, ASPECT, false);
+		let mut load_summary = load_existing_images(image_dir, &mut card_images, false, recursive);
+		let mut sample_cache = open_sample_cache(image_dir, &mut card_images, &mut load_summary, &sample_sizes, aspect, &variants);
+		print_load_summary(&load_summary);
 		println!("Loaded {} card images!", card_images.len());
 
+		if owned_only {
+			/* every copy is its own card, so no copy is placed twice and no duplicates are made up */
+			let usage_caps = restrict_to_owned(image_dir, &mut card_images);
+			expand_owned_copies(&mut card_images, &usage_caps);
+			println!("{} owned copies in all", card_images.len());
+		}
+		if card_images.is_empty() { panic!("No cards to place!"); }
+
 		println!("Loading base image...");
-		let base_image = image::load_from_memory(fs::read(BASE_IMAGE_DIR).unwrap().as_slice()).unwrap();
+		let base_image = load_base_image(mask_path);
 
 		println!("Creating card grid...");
-		let mut card_grid = create_grid_fitting(card_images.len() as u32, ASPECT, base_image.width(), base_image.height(), false);
+		let num_cards = card_images.len() as u32;
+		let shape = create_shape(&base_image, num_cards, sample_width, sample_height);
+		let mut card_grid = create_grid_fitting(num_cards, aspect, &base_image, shape.as_ref(), owned_only, layout);
+
+		/* offset layouts can have a few more tiles than cells, search for the most cards whose grid the owned copies still cover */
+		if owned_only && count_opaque_tiles(shape.as_ref(), &card_grid) > num_cards {
+			let (mut fewest, mut most) = (1, num_cards.max(1) - 1);
+			while fewest < most {
+				let fit_cards = (fewest + most).div_ceil(2);
+				let fit_grid = create_grid_fitting(fit_cards, aspect, &base_image, shape.as_ref(), true, layout);
+				if count_opaque_tiles(shape.as_ref(), &fit_grid) > num_cards { most = fit_cards - 1; } else { fewest = fit_cards; }
+			}
+			card_grid = create_grid_fitting(fewest, aspect, &base_image, shape.as_ref(), true, layout);
+		}
 		println!("Created a {} x {} card grid", card_grid.cards_wide, card_grid.cards_tall);
-		println!("{} total spaces, {} unused", card_grid.cards_wide * card_grid.cards_tall, card_images.len() as i32 - (card_grid.cards_wide * card_grid.cards_tall) as i32);
+		/* the grid is already sized to the cards, merging would leave some of them out */
+		if max_span > 1 { println!("Not merging tiles, new gives every card its own cell"); }
+		remove_transparent_tiles(shape.as_ref(), &mut card_grid);
+		println!("{} total tiles, {} unused", card_grid.tiles.len(), card_images.len() as i32 - card_grid.tiles.len() as i32);
 
-		let needed_duplicates = ((card_grid.cards_wide * card_grid.cards_tall) - card_images.len() as u32).max(0);
+		let needed_duplicates = (card_grid.tiles.len() as u32).saturating_sub(card_images.len() as u32);
 		println!("Adding {} duplicates", needed_duplicates);
 		add_duplicates(&mut card_images, needed_duplicates);
 
 		println!("Populating card grid...");
-		populate_grid_new(&base_image, &card_images, &mut card_grid, SAMPLE_SIZE);
+		populate_grid_new(&base_image, &card_images, &mut sample_cache, &mut card_grid, match_width, match_height, aspect, &variants, pyramid);
+		save_sample_cache(&mut sample_cache).unwrap();
+
+		let image_width = output_image_width(print_size, dpi, &card_grid, aspect, IMAGE_WIDTH);
+		warn_upscaled(&card_grid, &card_images, aspect, image_width);
+
+		println!("Drawing and saving final result...");
+		let mut renderer = create_renderer(&card_grid, &card_images, aspect, image_width, tile_style, kernel, alpha);
+		let image_height = renderer.image_height;
+		save_output(&output_path, output_options, image_width, image_height, STRIP_HEIGHT, &mut |from_y, to_y| draw_strip(&mut renderer, from_y, to_y)).unwrap();
+
+		save_placement(manifest_path.as_deref(), plan_path.as_deref(), &card_grid, &card_images, image_dir, aspect, image_width);
+
+		if viewer {
+			println!("Writing viewer...");
+			write_viewer(&output_path.with_extension("html"), &output_path, output_format, &card_grid, &card_images, &load_metadata(image_dir)).unwrap();
+		}
 
-		println!("Drawing final result...");
-		let all_used_cards = card_images.iter().map(|_| true).collect::<Vec<bool>>();
-		let (card_draw_images, card_draw_indices) = create_draw_cards(&card_images, &all_used_cards, card_grid.cards_wide, IMAGE_WIDTH, ASPECT);
-		let output_image = draw_cards(&card_grid, card_draw_images, card_draw_indices, ASPECT, IMAGE_WIDTH);
+	} else if args[1] == "render" {
+		let manifest_file = args.get(2).expect("No manifest to render!");
 
-		println!("Saving final result...");
-		output_image.save_with_format("./test/sampled.png", ImageFormat::Png).unwrap();
+		/* matching is skipped, the cards go where the manifest says */
+		let manifest = load_manifest(Path::new(manifest_file)).unwrap();
+		let (card_grid, card_images) = manifest_grid(&manifest, image_dir);
+		let aspect = manifest.card_aspect;
+		println!("Loaded {} tiles of {} cards!", card_grid.tiles.len(), card_images.len());
+
+		let image_width = output_image_width(print_size, dpi, &card_grid, aspect, flag_value(&args, "--width").map(|width| width.parse::<u32>().expect("Width must be a number!")).unwrap_or(manifest.image_width));
+		warn_upscaled(&card_grid, &card_images, aspect, image_width);
+
+		let mut renderer = create_renderer(&card_grid, &card_images, aspect, image_width, tile_style, kernel, alpha);
+		let image_height = renderer.image_height;
+		save_output(&output_path, output_options, image_width, image_height, STRIP_HEIGHT, &mut |from_y, to_y| draw_strip(&mut renderer, from_y, to_y)).unwrap();
+
+		println!("Drew sampled image!");
+
+		save_placement(manifest_path.as_deref(), plan_path.as_deref(), &card_grid, &card_images, image_dir, aspect, image_width);
+
+		if viewer {
+			write_viewer(&output_path.with_extension("html"), &output_path, output_format, &card_grid, &card_images, &load_metadata(image_dir)).unwrap();
+		}
+
+	} else if args[1] == "plan" {
+		let manifest_file = args.get(2).expect("No manifest to plan from!");
+
+		let manifest = load_manifest(Path::new(manifest_file)).unwrap();
+		let plan_path = plan_path.unwrap_or_else(|| Path::new(manifest_file).with_extension("html"));
+		write_plan(&plan_path, &manifest, &load_metadata(image_dir)).unwrap();
+
+		println!("Wrote plan for {} cards to {}", manifest.cells.len(), plan_path.display());
+
+	} else if args[1] == "collection" && args.get(2).is_some_and(|command| command == "import") {
+		let collection_file = args.get(3).expect("No collection to import!");
+
+		setup_dir(image_dir).unwrap();
+		import_collection(Path::new(collection_file), image_dir, image_kind, crop_strategy).await.unwrap();
+
+	} else if args[1] == "library" && args.get(2).is_some_and(|command| command == "check") {
+		setup_dir(image_dir).unwrap();
+		check_library(image_dir, recursive, args.iter().any(|arg| arg == "--quarantine")).unwrap();
+
+	} else if args[1] == "import" {
+		let source_dir = args.get(2).expect("No folder to import from!");
+
+		setup_dir(image_dir).unwrap();
+		import_images(source_dir, image_dir, aspect, crop_strategy, recursive).unwrap();
 
 	} else {
 		println!("Invalid argument");
@@ -102,15 +331,34 @@ async fn main() {
 }
 
 #[derive(Deserialize)]
-struct ImageUris {
-	art_crop: String
+pub struct ImageUris {
+	art_crop: String,
+	normal: String,
+	large: String,
+	png: String
 }
 
 #[derive(Deserialize)]
 struct CardInfo {
 	id: String,
 	layout: String,
-	image_uris: ImageUris
+	image_uris: ImageUris,
+	name: String,
+	set: String,
+	set_name: String,
+	collector_number: String,
+	artist: Option<String>,
+	scryfall_uri: String
+}
+
+/**
+ * the value following a flag, like the "brick" in "--layout brick"
+ */
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+	args.iter()
+		.position(|arg| arg == flag)
+		.and_then(|index| args.get(index + 1))
+		.map(|value| value.as_str())
 }
 
 fn setup_dir(dir_path: &str) -> std::io::Result<()> {
@@ -121,25 +369,170 @@ fn setup_dir(dir_path: &str) -> std::io::Result<()> {
 	Ok(())
 }
 
-fn load_existing_images(dir_path: &str, card_images: &mut Vec<DynamicImage>, card_aspect: f32, crop: bool) {
-	let paths = fs::read_dir(dir_path).unwrap();
+/**
+ * loads the library's sample cache, dropping anything about files that changed or are gone,
+ * then makes every sample this run needs that isn't cached yet, so each card is decoded at most once
+ * cards that can't be decoded are dropped and moved to the quarantine folder
+ */
+fn open_sample_cache(
+	dir_path: &str,
+	card_images: &mut Vec<LibraryCard>,
+	load_summary: &mut LoadSummary,
+	sample_sizes: &[(u32, u32)],
+	card_aspect: f32,
+	variants: &[Variant],
+) -> SampleCache {
+	let mut sample_cache = load_sample_cache(dir_path);
+	let paths = card_images.iter().map(|card| card.path.as_path()).collect::<Vec<&Path>>();
+
+	validate_cache(&mut sample_cache, &paths);
+
+	let mut windows = variants.iter().map(|variant| variant.window).collect::<Vec<f32>>();
+	windows.dedup();
+
+	let mut broken = Vec::new();
+
+	card_images.retain(|card| {
+		let missing = sample_sizes.iter()
+			.flat_map(|(width, height)| windows.iter().map(move |window| (*width, *height, *window)))
+			.filter(|(width, height, window)| !has_sample(&sample_cache, &card.path, *width, *height, *window))
+			.collect::<Vec<(u32, u32, f32)>>();
+
+		if missing.is_empty() { return true; }
+
+		match try_load_card_image(card, card_aspect) {
+			Some(full_image) => {
+				for (width, height, window) in missing {
+					store_sample(&mut sample_cache, &card.path, window, &create_window_sample(&full_image, card_aspect, window, width, height));
+				}
+				true
+			},
+			None => {
+				broken.push(card.path.clone());
+				false
+			},
+		}
+	});
+
+	/* only left out of this run, library check --quarantine is what moves them */
+	load_summary.unreadable.extend(broken);
+
+	sample_cache
+}
+
+/**
+ * the base image, cut to the mask's shape if there is one
+ */
+fn load_base_image(mask_path: Option<&str>) -> DynamicImage {
+	let base_image = image::load_from_memory(fs::read(BASE_IMAGE_DIR).unwrap().as_slice()).unwrap();
+
+	match mask_path {
+		Some(mask_path) => apply_mask(&base_image, &image::open(mask_path).expect("Couldn't open mask!")),
+		None => base_image,
+	}
+}
 
-	for path in paths {
-		let path = path.unwrap().path();
-		let original_image = image::load_from_memory(fs::read(&path).unwrap().as_slice()).unwrap();
+/**
+ * the manifest and the plan made from it, whichever were asked for
+ */
+fn save_placement(manifest_path: Option<&Path>, plan_path: Option<&Path>, card_grid: &CardGrid, card_images: &[LibraryCard], dir_path: &str, card_aspect: f32, image_width: u32) {
+	if manifest_path.is_none() && plan_path.is_none() { return; }
+
+	let metadata = load_metadata(dir_path);
+	let manifest = create_manifest(card_grid, card_images, &metadata, card_aspect, image_width);
+
+	if let Some(manifest_path) = manifest_path {
+		println!("Writing manifest...");
+		save_manifest(manifest_path, &manifest).unwrap();
+	}
 
-		card_images.push(if crop { crop_card(original_image, card_aspect) } else { original_image });
+	if let Some(plan_path) = plan_path {
+		println!("Writing plan...");
+		write_plan(plan_path, &manifest, &metadata).unwrap();
 	}
 }
 
-async fn save_num_cards(dir_path: &str, card_images: &mut Vec<DynamicImage>, card_aspect: f32, num_cards: u32) -> Result<(), Box<dyn std::error::Error>> {
+/**
+ * narrows the library down to the imported collection, returning each card's usage cap
+ */
+fn restrict_to_owned(dir_path: &str, card_images: &mut Vec<LibraryCard>) -> Vec<u32> {
+	let collection = load_collection(dir_path).expect("No collection, run collection import first!");
+	restrict_to_collection(card_images, &collection, &load_metadata(dir_path))
+}
+
+/**
+ * maps every entry of a collection export to a scryfall id and saves the owned quantities
+ * printings that aren't in the library yet are pulled from scryfall
+ */
+async fn import_collection(collection_path: &Path, dir_path: &str, image_kind: ImageKind, crop_strategy: CropStrategy) -> Result<(), Box<dyn std::error::Error>> {
 	let client = reqwest::Client::new();
+	let card_aspect = image_kind_aspect(image_kind);
+
+	let entries = parse_collection(collection_path)?;
+	let mut metadata = load_metadata(dir_path);
+	let mut collection: HashMap<String, u32> = HashMap::new();
+	let mut pulled = 0u32;
+	let mut not_found: Vec<&CollectionEntry> = Vec::new();
+
+	for entry in &entries {
+		let card_id = match find_library_card(entry, &metadata) {
+			Some(card_id) => card_id,
+			None => {
+				tokio::time::sleep(std::time::Duration::from_millis(SCRYFALL_DELAY_MS)).await;
+
+				match get_owned_card(&client, entry, image_kind).await {
+					Ok((card_image, card_uuid, card_metadata)) => {
+						save_card(card_image, card_uuid, card_aspect, crop_strategy, dir_path, &mut Vec::new());
+
+						let card_id = card_metadata.id.clone();
+						metadata.insert(format!("{}.png", card_uuid), card_metadata);
+						save_metadata(dir_path, &metadata)?;
+
+						pulled += 1;
+						card_id
+					},
+					Err(_err) => {
+						not_found.push(entry);
+						continue;
+					},
+				}
+			},
+		};
+
+		*collection.entry(card_id).or_insert(0) += entry.quantity;
+	}
+
+	save_collection(dir_path, &collection)?;
+
+	println!("Imported {} cards, {} copies in all, pulled {} new printings", collection.len(), collection.values().sum::<u32>(), pulled);
+
+	if !not_found.is_empty() {
+		println!("Couldn't find {} entries on scryfall:", not_found.len());
+		for entry in not_found {
+			println!("\t{} ({} {})", entry.name, entry.set, entry.collector_number);
+		}
+	}
+
+	Ok(())
+}
+
+async fn save_num_cards(dir_path: &str, card_images: &mut Vec<LibraryCard>, image_kind: ImageKind, crop_strategy: CropStrategy, num_cards: u32) -> Result<(), Box<dyn std::error::Error>> {
+	let client = reqwest::Client::new();
+	let card_aspect = image_kind_aspect(image_kind);
 	let mut count = 0u32;
 
+	setup_dir(dir_path)?;
+
+	let mut metadata = load_metadata(dir_path);
+
 	while count < num_cards {
-		match get_card(&client).await {
-			Ok((card_image, card_uuid)) => {
-				save_card(card_image, card_uuid, card_aspect, dir_path, card_images);
+		match get_card(&client, image_kind).await {
+			Ok((card_image, card_uuid, card_metadata)) => {
+				save_card(card_image, card_uuid, card_aspect, crop_strategy, dir_path, card_images);
+
+				/* saved every card so an interrupted pull keeps what it got */
+				metadata.insert(format!("{}.png", card_uuid), card_metadata);
+				save_metadata(dir_path, &metadata)?;
 
 				println!("Got card {} out of {}!", count + 1, num_cards);
 
@@ -154,7 +547,7 @@ async fn save_num_cards(dir_path: &str, card_images: &mut Vec<DynamicImage>, car
 	Ok(())
 }
 
-async fn get_card(client: &Client) -> Result<(DynamicImage, Uuid), Box<dyn std::error::Error>> {
+async fn get_card(client: &Client, image_kind: ImageKind) -> Result<(DynamicImage, Uuid, CardMetadata), Box<dyn std::error::Error>> {
 	let response = client.get("https://api.scryfall.com/cards/random").send().await?;
 
 	let card_info = response.json::<CardInfo>().await?;
@@ -162,7 +555,29 @@ async fn get_card(client: &Client) -> Result<(DynamicImage, Uuid), Box<dyn std::
 	/* prevent tokens, double faced cards, other things that interfere with art */
 	if card_info.layout != NORMAL_LAYOUT { return Err(Box::new(std::io::Error::new(ErrorKind::Other, "Bad layout!"))) };
 
-	let response = client.get(&card_info.image_uris.art_crop).send().await?;
+	download_card(client, card_info, image_kind).await
+}
+
+/**
+ * the printing a collection entry names, by scryfall id, set and collector number, or name
+ * owned cards are used whatever their layout, as long as they have a single image
+ */
+async fn get_owned_card(client: &Client, entry: &CollectionEntry, image_kind: ImageKind) -> Result<(DynamicImage, Uuid, CardMetadata), Box<dyn std::error::Error>> {
+	let request = match &entry.scryfall_id {
+		Some(id) => client.get(&format!("https://api.scryfall.com/cards/{}", id)),
+		None if is_set_code(&entry.set) && !entry.collector_number.is_empty() =>
+			client.get(&format!("https://api.scryfall.com/cards/{}/{}", entry.set.to_lowercase(), entry.collector_number)),
+		None if is_set_code(&entry.set) => client.get("https://api.scryfall.com/cards/named").query(&[("exact", entry.name.as_str()), ("set", entry.set.as_str())]),
+		None => client.get("https://api.scryfall.com/cards/named").query(&[("exact", entry.name.as_str())]),
+	};
+
+	let card_info = request.send().await?.error_for_status()?.json::<CardInfo>().await?;
+
+	download_card(client, card_info, image_kind).await
+}
+
+async fn download_card(client: &Client, card_info: CardInfo, image_kind: ImageKind) -> Result<(DynamicImage, Uuid, CardMetadata), Box<dyn std::error::Error>> {
+	let response = client.get(image_kind_uri(image_kind, &card_info.image_uris)).send().await?;
 
 	let image_bytes = response.bytes().await?;
 
@@ -170,11 +585,21 @@ async fn get_card(client: &Client) -> Result<(DynamicImage, Uuid), Box<dyn std::
 
 	let uuid = Uuid::parse_str(card_info.id.as_str())?;
 
-	Ok((image, uuid))
+	let card_metadata = CardMetadata {
+		id: card_info.id,
+		name: card_info.name,
+		set: card_info.set,
+		set_name: card_info.set_name,
+		collector_number: card_info.collector_number,
+		artist: card_info.artist,
+		scryfall_uri: card_info.scryfall_uri,
+	};
+
+	Ok((image, uuid, card_metadata))
 }
 
-fn save_card(card_image: DynamicImage, card_uuid: Uuid, card_aspect: f32, dir_path: &str, card_images: &mut Vec<DynamicImage>) {
-	let cropped_image = crop_card(card_image, card_aspect);
+fn save_card(card_image: DynamicImage, card_uuid: Uuid, card_aspect: f32, crop_strategy: CropStrategy, dir_path: &str, card_images: &mut Vec<LibraryCard>) {
+	let library_image = library_image(card_image, card_aspect, crop_strategy);
 
 	/* build path to save card image to disk */
 	let mut save_path = String::from(dir_path);
@@ -182,10 +607,10 @@ fn save_card(card_image: DynamicImage, card_uuid: Uuid, card_aspect: f32, dir_pa
 	save_path.push_str(".png");
 
 	/* save card image to disk */
-	cropped_image.save_with_format(save_path, ImageFormat::Png).unwrap();
+	library_image.save_with_format(&save_path, ImageFormat::Png).unwrap();
 
 	/* add card image to card images list */
-	card_images.push(cropped_image);
+	card_images.push(LibraryCard { path: PathBuf::from(save_path), crop: false });
 }
 
 fn crop_card(card_image: DynamicImage, desired_aspect: f32) -> DynamicImage {
@@ -209,60 +634,117 @@ fn crop_card(card_image: DynamicImage, desired_aspect: f32) -> DynamicImage {
 	card_image.resize_to_fill(new_width, new_height, FilterType::Triangle)
 }
 
+/**
+ * the area covered by a single card, as a polygon measured in cells
+ * the card is stretched over the polygon's bounding box and cut to its shape
+ */
+pub struct Tile {
+	polygon: Vec<(f32, f32)>
+}
+
+/**
+ * grid holds the selected card for each tile, in the same order as tiles
+ * variants holds how each of those cards is cropped and turned
+ * costs holds how far each of those cards' samples was from its tile's
+ */
 pub struct CardGrid {
 	grid: Vec<u32>,
+	variants: Vec<Variant>,
+	costs: Vec<u32>,
+	tiles: Vec<Tile>,
+	layout: Layout,
 	cards_wide: u32,
 	cards_tall: u32
 }
 
-fn populate_grid(base_image: &DynamicImage, card_images: &Vec<DynamicImage>, used_cards: &mut Vec<bool>, card_grid: &mut CardGrid, sample_size: u32) {
-	let card_samples = create_card_samples(card_images, sample_size);
-	let sample_image = create_sample_image(base_image, sample_size, card_grid.cards_wide, card_grid.cards_tall);
+/**
+ * usage_caps limits how many tiles each card can go in, tiles are filled in order so earlier ones get first pick
+ */
+#[allow(clippy::too_many_arguments)]
+fn populate_grid(base_image: &DynamicImage, card_images: &[LibraryCard], sample_cache: &mut SampleCache, card_grid: &mut CardGrid, sample_width: u32, sample_height: u32, card_aspect: f32, variants: &[Variant], pyramid: Option<Pyramid>, usage_caps: Option<&[u32]>) {
+	if let Some(usage_caps) = usage_caps {
+		let owned = usage_caps.iter().sum::<u32>();
+		if (owned as usize) < card_grid.tiles.len() { panic!("Only {} owned cards for {} tiles, use new instead!", owned, card_grid.tiles.len()); }
+	}
+
+	let card_samples = create_card_samples(card_images, sample_cache, sample_width, sample_height, card_aspect, variants);
+	let sample_image = create_sample_image(base_image, sample_width, sample_height, card_grid.cards_wide, card_grid.cards_tall);
+	let alpha_image = create_alpha_image(base_image, sample_width, sample_height, card_grid.cards_wide, card_grid.cards_tall);
+	let tile_samples = create_tile_samples(&sample_image, alpha_image.as_ref(), card_grid, sample_width, sample_height);
+
+	let fine_samples = pyramid.map(|pyramid| {
+		let fine_image = create_sample_image(base_image, pyramid.fine_width, pyramid.fine_height, card_grid.cards_wide, card_grid.cards_tall);
+		let fine_alpha = create_alpha_image(base_image, pyramid.fine_width, pyramid.fine_height, card_grid.cards_wide, card_grid.cards_tall);
+		(
+			create_card_samples(card_images, sample_cache, pyramid.fine_width, pyramid.fine_height, card_aspect, variants),
+			create_tile_samples(&fine_image, fine_alpha.as_ref(), card_grid, pyramid.fine_width, pyramid.fine_height),
+		)
+	});
+
+	let mut available = (0..card_images.len() as u32).collect::<Vec<u32>>();
+	let mut remaining_uses = usage_caps.map(|usage_caps| usage_caps.to_vec());
+
+	for (tile_index, tile_sample) in tile_samples.iter().enumerate() {
+		let (selected_card, variant, cost) = match (pyramid, &fine_samples) {
+			(Some(pyramid), Some((fine_cards, fine_tiles))) => {
+				let candidates = coarse_candidates(tile_sample, &card_samples, &available, variants, sample_width, sample_height, pyramid.candidates);
+				select_best_card(&fine_tiles[tile_index], fine_cards, &candidates, pyramid.fine_width, pyramid.fine_height, variants)
+			},
+			_ => select_best_card(tile_sample, &card_samples, &available, sample_width, sample_height, variants),
+		};
 
-	for x in 0..card_grid.cards_wide {
-		for y in 0..card_grid.cards_tall {
-			let selected_card = select_best_card(&sample_image, &card_samples, sample_size, x, y);
-			card_grid.grid[(y * card_grid.cards_wide + x) as usize] = selected_card;
-			used_cards[selected_card as usize] = true;
+		if let Some(remaining_uses) = &mut remaining_uses {
+			remaining_uses[selected_card as usize] -= 1;
+			if remaining_uses[selected_card as usize] == 0 { available.retain(|card_index| *card_index != selected_card); }
 		}
+
+		card_grid.grid[tile_index] = selected_card;
+		card_grid.variants[tile_index] = variant;
+		card_grid.costs[tile_index] = cost;
 	}
 }
 
-fn create_card_samples(card_images: &Vec<DynamicImage>, sample_size: u32) -> Vec<RgbImage> {
-	card_images
-		.iter()
-		.map(|full_image| full_image.resize_exact(sample_size, sample_size, FilterType::CatmullRom).to_rgb8())
-		.collect::<Vec<RgbImage>>()
+fn create_window_sample(full_image: &DynamicImage, card_aspect: f32, window: f32, sample_width: u32, sample_height: u32) -> RgbImage {
+	crop_window(full_image, card_aspect, window).resize_exact(sample_width, sample_height, FilterType::CatmullRom).to_rgb8()
 }
 
 /**
- * creates a list of card images to draw
- * will be approximately 2 times the size that they will be drawn at
+ * one sample of each card per variant, in the same order as variants
+ * samples already in the cache are reused, new ones are added to it
  */
-fn create_draw_cards(card_images: &Vec<DynamicImage>, used_cards: &Vec<bool>, cards_wide: u32, image_width: u32, card_aspect: f32) -> (Vec<RgbImage>, Vec<usize>) {
-	let card_width = (image_width as f32 / cards_wide as f32) * 2f32;
-
-	let card_height = (card_width * (1f32 / card_aspect)).round() as u32;
-	let card_width = card_width.round() as u32;
-
-	let mut resized_card_indices = vec![0usize; card_images.len()];
-	let mut resized_index = 0usize;
-
-	let resized_cards = card_images
-		.iter().enumerate()
-		.filter_map(|(full_index, full_image)| if used_cards[full_index] {
-			let resized_image = full_image.resize_exact(card_width, card_height, FilterType::Triangle).to_rgb8();
-
-			resized_card_indices[full_index] = resized_index;
-			resized_index += 1;
-
-			Some(resized_image)
-		} else {
-			None
+fn create_card_samples(card_images: &[LibraryCard], sample_cache: &mut SampleCache, sample_width: u32, sample_height: u32, card_aspect: f32, variants: &[Variant]) -> Vec<Vec<RgbImage>> {
+	card_images
+		.iter()
+		.map(|card| {
+			let mut window_samples: Vec<(f32, RgbImage)> = Vec::new();
+			/* only decoded if some sample isn't cached yet */
+			let mut full_image: Option<DynamicImage> = None;
+
+			variants.iter()
+				.map(|variant| {
+					/* each window only needs to be cut out once for all its transforms */
+					let sample = match window_samples.iter().find(|(window, _)| *window == variant.window) {
+						Some((_, sample)) => sample,
+						None => {
+							let sample = match lookup_sample(sample_cache, &card.path, sample_width, sample_height, variant.window) {
+								Some(sample) => sample,
+								None => {
+									let full_image = full_image.get_or_insert_with(|| load_card_image(card, card_aspect));
+									let sample = create_window_sample(full_image, card_aspect, variant.window, sample_width, sample_height);
+									store_sample(sample_cache, &card.path, variant.window, &sample);
+									sample
+								}
+							};
+							window_samples.push((variant.window, sample));
+							&window_samples.last().unwrap().1
+						}
+					};
+
+					apply_transform(sample, variant.transform)
+				})
+				.collect::<Vec<RgbImage>>()
 		})
-		.collect::<Vec<RgbImage>>();
-
-	(resized_cards, resized_card_indices)
+		.collect::<Vec<Vec<RgbImage>>>()
 }
 
 fn cards_tall(cards_wide: u32, card_aspect: f32, image_width: u32, image_height: u32) -> u32 {
@@ -281,28 +763,45 @@ fn cards_wide(cards_tall: u32, card_aspect: f32, image_width: u32, image_height:
 	(image_width as f32 / card_width).round() as u32
 }
 
-fn create_grid(cards_wide: u32, card_aspect: f32, image_width: u32, image_height: u32) -> CardGrid {
-	let cards_tall = cards_tall(cards_wide, card_aspect, image_width, image_height);
+fn create_grid(cards_wide: u32, card_aspect: f32, image_width: u32, image_height: u32, layout: Layout) -> CardGrid {
+	create_grid_sized(cards_wide, cards_tall(cards_wide, card_aspect, image_width, image_height), layout)
+}
 
-	let grid = vec![0u32; (cards_wide * cards_tall) as usize];
-	CardGrid { grid, cards_wide, cards_tall }
+fn create_grid_sized(cards_wide: u32, cards_tall: u32, layout: Layout) -> CardGrid {
+	let tiles = create_layout_tiles(layout, cards_wide, cards_tall);
+	let grid = vec![0u32; tiles.len()];
+	let variants = vec![CENTER_VARIANT; tiles.len()];
+	let costs = vec![0u32; tiles.len()];
+	CardGrid { grid, variants, costs, tiles, layout, cards_wide, cards_tall }
 }
 
-fn create_grid_fitting(n: u32, card_aspect: f32, image_width: u32, image_height: u32, under: bool) -> CardGrid {
+/**
+ * the grid closest to n cells, over or under
+ * with a shape only the tiles inside it count, so n cards fill the shape instead of the whole image
+ */
+fn create_grid_fitting(n: u32, card_aspect: f32, base_image: &DynamicImage, shape: Option<&Shape>, under: bool, layout: Layout) -> CardGrid {
 	struct Result {
 		width: u32,
 		height: u32,
+		cells: u32,
 	}
-	impl Result {
-		fn size(&self) -> u32 {
-			self.width * self.height
+
+	/* no grid would ever have enough tiles inside an empty shape */
+	if shape.is_some_and(|shape| !has_opaque_part(shape)) { panic!("The base image has nothing opaque to put cards on, check the mask!"); }
+
+	let (image_width, image_height) = base_image.dimensions();
+	let cells = |width: u32, height: u32| -> u32 {
+		if shape.is_some() && width > 0 && height > 0 {
+			count_opaque_tiles(shape, &create_grid_sized(width, height, layout))
+		} else {
+			width * height
 		}
-	}
+	};
 
 	let mut increment_width = 0;
 	loop {
 		let height = cards_tall(increment_width, card_aspect, image_width, image_height);
-		if increment_width * height >= n {
+		if cells(increment_width, height) >= n {
 			if under { increment_width -= 1 };
 			break;
 		}
@@ -312,40 +811,52 @@ fn create_grid_fitting(n: u32, card_aspect: f32, image_width: u32, image_height:
 	let mut increment_height = 0;
 	loop {
 		let width = cards_wide(increment_height, card_aspect, image_width, image_height);
-		if width * increment_height >= n {
+		if cells(width, increment_height) >= n {
 			if under { increment_height -= 1 };
 			break;
 		}
 		increment_height += 1;
 	}
 
+	let result = |width: u32, height: u32| Result { width, height, cells: cells(width, height) };
 	let results = [
-		Result { width: cards_wide(increment_height, card_aspect, image_width, image_height), height: increment_height },
-		Result { width: increment_width, height: cards_tall(increment_width, card_aspect, image_width, image_height) },
+		result(cards_wide(increment_height, card_aspect, image_width, image_height), increment_height),
+		result(increment_width, cards_tall(increment_width, card_aspect, image_width, image_height)),
 	];
 
-	let Result { width, height } = if under {
+	let Result { width, height, .. } = if under {
 		results.iter()
-			.max_by(|result0, result1| result0.size().partial_cmp(&result1.size()).unwrap())
+			.max_by(|result0, result1| result0.cells.partial_cmp(&result1.cells).unwrap())
 			.unwrap()
 	} else {
 		results.iter()
-			.min_by(|result0, result1| result0.size().partial_cmp(&result1.size()).unwrap())
+			.min_by(|result0, result1| result0.cells.partial_cmp(&result1.cells).unwrap())
 			.unwrap()
 	};
 
-	CardGrid {
-		grid: vec![0u32; (width * height) as usize],
-		cards_wide: *width,
-		cards_tall: *height
+	create_grid_sized(*width, *height, layout)
+}
+
+/**
+ * (width, height) of a sample with the card's aspect, short side being sample_size
+ */
+fn sample_dimensions(sample_size: u32, card_aspect: f32) -> (u32, u32) {
+	if card_aspect >= 1.0_f32 {
+		((sample_size as f32 * card_aspect).round() as u32, sample_size)
+	} else {
+		(sample_size, (sample_size as f32 / card_aspect).round() as u32)
 	}
 }
 
-fn create_sample_image(base_image: &DynamicImage, sample_size: u32, cards_wide: u32, cards_tall: u32) -> RgbImage {
-	base_image.resize_exact(cards_wide * sample_size, cards_tall * sample_size, FilterType::Triangle).to_rgb8()
+fn create_sample_image(base_image: &DynamicImage, sample_width: u32, sample_height: u32, cards_wide: u32, cards_tall: u32) -> RgbImage {
+	base_image.resize_exact(cards_wide * sample_width, cards_tall * sample_height, FilterType::Triangle).to_rgb8()
 }
 
-fn select_best_card(sample_image: &RgbImage, card_images: &Vec<RgbImage>, sample_size: u32, grid_x: u32, grid_y: u32) -> u32 {
+/**
+ * only the cards listed in candidates are considered
+ * returns (card, variant, difference)
+ */
+fn select_best_card(tile_sample: &TileSample, card_images: &[Vec<RgbImage>], candidates: &[u32], sample_width: u32, sample_height: u32, variants: &[Variant]) -> (u32, Variant, u32) {
 	fn pixel_at(bytes: &[u8], width: u32, x: u32, y: u32) -> [u8; 3] {
 		[bytes[((width * y + x) * 3u32) as usize], bytes[((width * y + x) * 3u32 + 1) as usize], bytes[((width * y + x) * 3u32 + 2) as usize]]
 	}
@@ -358,97 +869,33 @@ fn select_best_card(sample_image: &RgbImage, card_images: &Vec<RgbImage>, sample
 
 	let mut least_dif = u32::max_value();
 	let mut best_card = 0u32;
+	let mut best_variant = CENTER_VARIANT;
 
-	for (card_index, card_image) in card_images.iter().enumerate() {
-		let mut current_dif = 0u32;
+	for &card_index in candidates {
+		let card_variants = &card_images[card_index as usize];
 
-		/* all pixels sampled from the card image */
-		for sample_x in 0..sample_size {
-			let base_x = grid_x * sample_size + sample_x;
+		for (card_image, variant) in card_variants.iter().zip(variants.iter()) {
+			let mut current_dif = 0u32;
 
-			for sample_y in 0..sample_size {
-				let base_y = grid_y * sample_size + sample_y;
+			/* all pixels sampled from the card image */
+			for sample_x in 0..sample_width {
+				for sample_y in 0..sample_height {
+					if !tile_sample.mask[(sample_y * sample_width + sample_x) as usize] { continue; }
 
-				current_dif += pixel_difference(
-					pixel_at(sample_image.as_bytes(), sample_image.width(), base_x, base_y),
-					pixel_at(card_image.as_bytes(), sample_size, sample_x, sample_y)
-				);
+					current_dif += pixel_difference(
+						pixel_at(tile_sample.image.as_bytes(), sample_width, sample_x, sample_y),
+						pixel_at(card_image.as_bytes(), sample_width, sample_x, sample_y)
+					);
+				}
 			}
-		}
-
-		if current_dif < least_dif {
-			least_dif = current_dif;
-			best_card = card_index as u32;
-		}
-	}
-
-	best_card
-}
-
-fn draw_cards(card_grid: &CardGrid, card_draw_images: Vec<RgbImage>, card_draw_indices: Vec<usize>, card_aspect: f32, image_width: u32) -> RgbImage {
-	fn bilinear(bytes: &[u8], width: u32, height: u32, x: f32, y: f32) -> [u8; 3] {
-		let pixel_x0 = x as u32;
-		let pixel_x1 = (pixel_x0 + 1u32).min(width - 1);
-		let weight_x1 = pixel_x0 as f32 - x;
-		let weight_x0 = 1f32 - weight_x1;
-
-		let pixel_y0 = y as u32;
-		let pixel_y1 = (pixel_y0 + 1u32).min(height - 1);
-		let weight_y1 = pixel_y0 as f32 - y;
-		let weight_y0 = 1f32 - weight_y1;
-
-		let channel_weight = |offset: u32| -> u8 {
-			(
-				(bytes[((pixel_y0 * width + pixel_x0) * 3u32 + offset) as usize] as f32 * weight_x0 * weight_y0) +
-				(bytes[((pixel_y1 * width + pixel_x0) * 3u32 + offset) as usize] as f32 * weight_x0 * weight_y1) +
-				(bytes[((pixel_y0 * width + pixel_x1) * 3u32 + offset) as usize] as f32 * weight_x1 * weight_y0) +
-				(bytes[((pixel_y1 * width + pixel_x1) * 3u32 + offset) as usize] as f32 * weight_x1 * weight_y1)
-			).round() as u8
-		};
-
-		[channel_weight(0), channel_weight(1), channel_weight(2)]
-	}
-
-	fn put_pixel(bytes: &mut [u8], pixel: &[u8;3], width: u32, x: u32, y: u32) {
-		bytes[((y * width + x) * 3    ) as usize] = pixel[0];
-		bytes[((y * width + x) * 3 + 1) as usize] = pixel[1];
-		bytes[((y * width + x) * 3 + 2) as usize] = pixel[2];
-	}
 
-	let card_width = image_width as f32 / card_grid.cards_wide as f32;
-	let card_height = (1f32 / card_aspect) * card_width;
-
-	let image_height = (card_height * card_grid.cards_tall as f32).round() as u32;
-
-	let mut draw_bytes = vec![0u8; (image_width * image_height * 3) as usize];
-
-	for x in 0..card_grid.cards_wide {
-		let min_x = (x as f32 * card_width).round() as u32;
-		let max_x = ((x + 1u32) as f32 * card_width).round() as u32;
-		let x_len = max_x - min_x;
-
-		for y in 0..card_grid.cards_tall {
-			let card_image = &card_draw_images[card_draw_indices[card_grid.grid[(y * card_grid.cards_wide + x) as usize] as usize]];
-			let card_bytes = card_image.as_bytes();
-
-			let min_y = (y as f32 * card_height).round() as u32;
-			let max_y = ((y + 1u32) as f32 * card_height).round() as u32;
-			let y_len = max_y - min_y;
-
-			for x_along in 0..x_len {
-				let draw_x = x_along + min_x;
-				let card_x = (x_along as f32 / x_len as f32) * card_image.width() as f32;
-
-				for y_along in 0..y_len {
-					let draw_y = y_along + min_y;
-					let card_y = (y_along as f32 / y_len as f32) * card_image.height() as f32;
-
-					let pixel = bilinear(card_bytes, card_image.width(), card_image.height(), card_x, card_y);
-					put_pixel(&mut draw_bytes, &pixel, image_width, draw_x, draw_y);
-				}
+			if current_dif < least_dif {
+				least_dif = current_dif;
+				best_card = card_index;
+				best_variant = *variant;
 			}
 		}
 	}
 
-	RgbImage::from_raw(image_width, image_height, draw_bytes).unwrap()
+	(best_card, best_variant, least_dif)
 }
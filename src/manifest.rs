@@ -0,0 +1,254 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use crate::{CardGrid, Tile};
+use crate::library::LibraryCard;
+use crate::layout::{tile_bounds, layout_name, parse_layout};
+use crate::metadata::{CardMetadata, card_metadata};
+use crate::render::{render_dimensions, tile_pixel_bounds};
+use crate::transform::{transform_name, parse_transform};
+use crate::window::Variant;
+
+/**
+ * where every card of a mosaic went and how well it matched
+ * json manifests hold everything needed to draw the mosaic again
+ */
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+	pub layout: String,
+	pub card_aspect: f32,
+	pub cards_wide: u32,
+	pub cards_tall: u32,
+	pub image_width: u32,
+	pub image_height: u32,
+	pub stats: ManifestStats,
+	pub cells: Vec<ManifestCell>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestStats {
+	pub tiles: u32,
+	pub unique_cards: u32,
+	/* tiles showing a card that's already shown somewhere before them */
+	pub duplicates: u32,
+	pub most_uses: u32,
+	pub total_cost: u64,
+	pub mean_cost: f32,
+	pub max_cost: u32,
+}
+
+/**
+ * one tile, x, y, width and height in cells and pixel_* in pixels of the output
+ * duplicate counts the tiles before this one showing the same card, 0 for its first use
+ */
+#[derive(Serialize, Deserialize)]
+pub struct ManifestCell {
+	pub index: u32,
+	pub x: f32,
+	pub y: f32,
+	pub width: f32,
+	pub height: f32,
+	pub polygon: Vec<(f32, f32)>,
+	pub pixel_x: i32,
+	pub pixel_y: i32,
+	pub pixel_width: i32,
+	pub pixel_height: i32,
+	pub file: String,
+	pub uuid: Option<String>,
+	pub cost: u32,
+	pub window: f32,
+	pub transform: String,
+	pub duplicate: u32,
+}
+
+const CSV_HEADER: &str = "index,x,y,width,height,pixel_x,pixel_y,pixel_width,pixel_height,file,uuid,cost,window,transform,duplicate";
+
+/**
+ * the scryfall id a card was pulled with, or the uuid it was named after
+ */
+fn card_uuid(card: &LibraryCard, metadata: &HashMap<String, CardMetadata>) -> Option<String> {
+	card_metadata(metadata, &card.path)
+		.map(|card_metadata| card_metadata.id.clone())
+		.or_else(|| card.path.file_stem().and_then(|stem| Uuid::parse_str(&stem.to_string_lossy()).ok()).map(|uuid| uuid.to_string()))
+}
+
+pub fn create_manifest(card_grid: &CardGrid, card_images: &[LibraryCard], metadata: &HashMap<String, CardMetadata>, card_aspect: f32, image_width: u32) -> Manifest {
+	let (_, _, image_height) = render_dimensions(card_grid, card_aspect, image_width);
+	let tile_pixels = tile_pixel_bounds(card_grid, card_aspect, image_width);
+
+	/* duplicates added for new are separate cards of the same file, so uses are counted by file */
+	let mut uses: HashMap<&Path, u32> = HashMap::new();
+
+	let cells = card_grid.tiles.iter().enumerate().map(|(tile_index, tile)| {
+		let card = &card_images[card_grid.grid[tile_index] as usize];
+		let variant = card_grid.variants[tile_index];
+		let (min_x, min_y, max_x, max_y) = tile_bounds(tile);
+		let (pixel_min_x, pixel_min_y, pixel_max_x, pixel_max_y) = tile_pixels[tile_index];
+
+		let card_uses = uses.entry(card.path.as_path()).or_insert(0);
+		let duplicate = *card_uses;
+		*card_uses += 1;
+
+		ManifestCell {
+			index: tile_index as u32,
+			x: min_x,
+			y: min_y,
+			width: max_x - min_x,
+			height: max_y - min_y,
+			polygon: tile.polygon.clone(),
+			pixel_x: pixel_min_x,
+			pixel_y: pixel_min_y,
+			pixel_width: pixel_max_x - pixel_min_x,
+			pixel_height: pixel_max_y - pixel_min_y,
+			file: card.path.to_string_lossy().into_owned(),
+			uuid: card_uuid(card, metadata),
+			cost: card_grid.costs[tile_index],
+			window: variant.window,
+			transform: transform_name(variant.transform).to_string(),
+			duplicate,
+		}
+	}).collect::<Vec<ManifestCell>>();
+
+	let total_cost = cells.iter().map(|cell| cell.cost as u64).sum::<u64>();
+
+	let stats = ManifestStats {
+		tiles: cells.len() as u32,
+		unique_cards: uses.len() as u32,
+		duplicates: cells.iter().filter(|cell| cell.duplicate > 0).count() as u32,
+		most_uses: uses.values().copied().max().unwrap_or(0),
+		total_cost,
+		mean_cost: if cells.is_empty() { 0.0_f32 } else { total_cost as f32 / cells.len() as f32 },
+		max_cost: cells.iter().map(|cell| cell.cost).max().unwrap_or(0),
+	};
+
+	Manifest {
+		layout: layout_name(card_grid.layout).to_string(),
+		card_aspect,
+		cards_wide: card_grid.cards_wide,
+		cards_tall: card_grid.cards_tall,
+		image_width,
+		image_height,
+		stats,
+		cells,
+	}
+}
+
+/**
+ * quotes a csv field if it needs it
+ */
+fn csv_field(text: &str) -> String {
+	if text.contains([',', '"', '\n']) {
+		format!("\"{}\"", text.replace('"', "\"\""))
+	} else {
+		text.to_string()
+	}
+}
+
+/**
+ * one row per tile under a plain header, so any csv reader takes it as is
+ * polygons are left out, only json manifests can be drawn again
+ */
+fn manifest_csv(manifest: &Manifest) -> String {
+	let mut csv = format!("{}\n", CSV_HEADER);
+
+	for cell in &manifest.cells {
+		csv.push_str(&format!(
+			"{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+			cell.index, cell.x, cell.y, cell.width, cell.height,
+			cell.pixel_x, cell.pixel_y, cell.pixel_width, cell.pixel_height,
+			csv_field(&cell.file), cell.uuid.as_deref().unwrap_or(""),
+			cell.cost, cell.window, cell.transform, cell.duplicate,
+		));
+	}
+
+	csv
+}
+
+/**
+ * the grid and stats of a csv manifest, as name,value rows in a file of their own
+ */
+fn manifest_stats_csv(manifest: &Manifest) -> String {
+	let stats = &manifest.stats;
+
+	format!(
+		"name,value\nlayout,{}\ncards,{}x{}\nimage,{}x{}\ntiles,{}\nunique_cards,{}\nduplicates,{}\nmost_uses,{}\ntotal_cost,{}\nmean_cost,{}\nmax_cost,{}\n",
+		manifest.layout,
+		manifest.cards_wide, manifest.cards_tall,
+		manifest.image_width, manifest.image_height,
+		stats.tiles, stats.unique_cards, stats.duplicates, stats.most_uses, stats.total_cost, stats.mean_cost, stats.max_cost,
+	)
+}
+
+/**
+ * where a csv manifest's stats go, name_stats.csv next to it
+ */
+fn stats_path(path: &Path) -> PathBuf {
+	let stem = path.file_stem().unwrap().to_string_lossy();
+	path.with_file_name(format!("{}_stats.csv", stem))
+}
+
+/**
+ * csv for a .csv path, with its stats in a second csv file, json for anything else
+ */
+pub fn save_manifest(path: &Path, manifest: &Manifest) -> Result<(), Box<dyn std::error::Error>> {
+	if let Some(parent) = path.parent() { fs::create_dir_all(parent)?; }
+
+	let csv = path.extension().is_some_and(|extension| extension.to_string_lossy().eq_ignore_ascii_case("csv"));
+
+	if csv {
+		fs::write(path, manifest_csv(manifest))?;
+		fs::write(stats_path(path), manifest_stats_csv(manifest))?;
+	} else {
+		fs::write(path, serde_json::to_string_pretty(manifest)?)?;
+	}
+
+	Ok(())
+}
+
+pub fn load_manifest(path: &Path) -> Result<Manifest, Box<dyn std::error::Error>> {
+	if path.extension().is_some_and(|extension| extension.to_string_lossy().eq_ignore_ascii_case("csv")) {
+		return Err("Csv manifests have no tile shapes, render from a json one!".into());
+	}
+
+	Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/**
+ * the card grid a manifest was saved from, and one library card per file it uses
+ * files that have moved are looked for by name in library_dir
+ */
+pub fn manifest_grid(manifest: &Manifest, library_dir: &str) -> (CardGrid, Vec<LibraryCard>) {
+	let mut card_images: Vec<LibraryCard> = Vec::new();
+	let mut card_indices: HashMap<&str, u32> = HashMap::new();
+
+	let grid = manifest.cells.iter().map(|cell| {
+		*card_indices.entry(cell.file.as_str()).or_insert_with(|| {
+			let mut path = PathBuf::from(&cell.file);
+
+			if !path.exists() {
+				path = Path::new(library_dir).join(path.file_name().expect("Manifest card without a file name!"));
+				if !path.exists() { panic!("{} from the manifest isn't in the library!", cell.file); }
+			}
+
+			card_images.push(LibraryCard { path, crop: false });
+			card_images.len() as u32 - 1
+		})
+	}).collect::<Vec<u32>>();
+
+	let card_grid = CardGrid {
+		grid,
+		variants: manifest.cells.iter().map(|cell| Variant {
+			window: cell.window,
+			transform: parse_transform(&cell.transform).expect("Unknown transform in manifest!"),
+		}).collect::<Vec<Variant>>(),
+		costs: manifest.cells.iter().map(|cell| cell.cost).collect::<Vec<u32>>(),
+		tiles: manifest.cells.iter().map(|cell| Tile { polygon: cell.polygon.clone() }).collect::<Vec<Tile>>(),
+		layout: parse_layout(&manifest.layout).expect("Unknown layout in manifest!"),
+		cards_wide: manifest.cards_wide,
+		cards_tall: manifest.cards_tall,
+	};
+
+	(card_grid, card_images)
+}
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/* stored in the library folder, keyed by card file name */
+pub const METADATA_FILE: &str = ".cards.json";
+
+/**
+ * what scryfall told us about a card when it was pulled
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CardMetadata {
+	pub id: String,
+	pub name: String,
+	pub set: String,
+	pub set_name: String,
+	pub collector_number: String,
+	pub artist: Option<String>,
+	pub scryfall_uri: String,
+}
+
+/**
+ * a missing or unreadable file is just no metadata, imported cards never have any
+ */
+pub fn load_metadata(dir_path: &str) -> HashMap<String, CardMetadata> {
+	fs::read(Path::new(dir_path).join(METADATA_FILE)).ok()
+		.and_then(|bytes| serde_json::from_slice(&bytes).ok())
+		.unwrap_or_default()
+}
+
+pub fn save_metadata(dir_path: &str, metadata: &HashMap<String, CardMetadata>) -> std::io::Result<()> {
+	fs::write(Path::new(dir_path).join(METADATA_FILE), serde_json::to_vec_pretty(metadata)?)
+}
+
+/**
+ * metadata for a library card, by its file name
+ */
+pub fn card_metadata<'a>(metadata: &'a HashMap<String, CardMetadata>, path: &Path) -> Option<&'a CardMetadata> {
+	metadata.get(path.file_name()?.to_string_lossy().as_ref())
+}
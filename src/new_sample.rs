@@ -1,5 +1,11 @@
 use image::{DynamicImage, RgbImage, EncodableLayout, ImageFormat};
-use crate::{CardGrid, create_card_samples, create_sample_image, get_card};
+use crate::{CardGrid, Tile, create_card_samples, create_sample_image};
+use crate::layout::{TileSample, create_tile_samples, tile_center};
+use crate::alpha::create_alpha_image;
+use crate::window::Variant;
+use crate::pyramid::Pyramid;
+use crate::library::LibraryCard;
+use crate::cache::SampleCache;
 use crate::preprocess::{add_duplicates, count_brightness, create_brightness_counts, create_brightness_map, match_brightness};
 
 pub fn pixel_at(bytes: &[u8], width: u32, x: u32, y: u32) -> (u8, u8, u8) {
@@ -16,47 +22,70 @@ fn pixel_difference(pixel0: (u8, u8, u8), pixel1: (u8, u8, u8)) -> u32 {
 	(pixel0.2 as i32 - pixel1.2 as i32).abs() as u32
 }
 
+/**
+ * pixels outside the mask are skipped,
+ * the total is scaled back up so partially covered tiles compare fairly with full ones
+ */
+#[allow(clippy::too_many_arguments)]
 fn card_dif(
 	base_bytes: &[u8],
     base_width: u32,
     card_bytes: &[u8],
     card_width: u32,
+    mask: &[bool],
     x: u32,
     y: u32,
-    sample_size: u32,
+    sample_width: u32,
+    sample_height: u32,
 ) -> u32 {
 	let mut total_difference = 0;
+	let mut counted = 0;
+
+	for j in 0..sample_height {
+		for i in 0..sample_width {
+			if !mask[(j * sample_width + i) as usize] { continue; }
 
-	for j in 0..sample_size {
-		for i in 0..sample_size {
 			total_difference += pixel_difference(
 				pixel_at(base_bytes, base_width,x + i, y + j),
 				pixel_at(card_bytes, card_width, i, j),
 			);
+			counted += 1;
 		}
 	}
 
-	total_difference
+	if counted == 0 { return 0; }
+
+	total_difference * (sample_width * sample_height) / counted
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn populate_grid_new(
 	base_image: &DynamicImage,
-	card_images: &Vec<DynamicImage>,
+	card_images: &[LibraryCard],
+	sample_cache: &mut SampleCache,
 	card_grid: &mut CardGrid,
-	sample_size: u32,
+	sample_width: u32,
+	sample_height: u32,
+	card_aspect: f32,
+	variants: &[Variant],
+	pyramid: Option<Pyramid>,
 ) {
 	println!("Creating sample image...");
-	let sample_image = create_sample_image(base_image, sample_size, card_grid.cards_wide, card_grid.cards_tall);
+	let sample_image = create_sample_image(base_image, sample_width, sample_height, card_grid.cards_wide, card_grid.cards_tall);
 	println!("Creating sample cards...");
-	let mut sample_cards = create_card_samples(card_images, sample_size);
+	let sample_cards = create_card_samples(card_images, sample_cache, sample_width, sample_height, card_aspect, variants);
+
+	let alpha_image = create_alpha_image(base_image, sample_width, sample_height, card_grid.cards_wide, card_grid.cards_tall);
 
 	println!("brightness preprocessing...");
 	let mut base_brightness_counts = create_brightness_counts();
 	let mut card_brightness_counts = create_brightness_counts();
 
-	count_brightness(&sample_image, &mut base_brightness_counts);
-	for card in &sample_cards {
-		count_brightness(card, &mut card_brightness_counts);
+	/* transparent parts of the base image get no cards, so they don't pull the brightness of the rest */
+	count_brightness(&sample_image, alpha_image.as_ref(), &mut base_brightness_counts);
+	for card_variants in &sample_cards {
+		/* variants are mostly the same pixels, only count the first */
+		count_brightness(&card_variants[0], None, &mut card_brightness_counts);
 	}
 
 	let brightness_map = create_brightness_map(&base_brightness_counts, &card_brightness_counts);
@@ -64,48 +93,80 @@ pub fn populate_grid_new(
 
 	sample_image.save_with_format("./test/brightness-matched.png", ImageFormat::Png).unwrap();
 
+	let tile_samples = create_tile_samples(&sample_image, alpha_image.as_ref(), card_grid, sample_width, sample_height);
+
 	println!("Ranking cards...");
 	let mut columns = rank_all_cards(
-		&sample_image,
+		&tile_samples,
+		&card_grid.tiles,
 		&sample_cards,
+		variants,
 		card_grid.cards_wide,
 		card_grid.cards_tall,
-		sample_size,
+		sample_width,
+		sample_height,
 	);
 
+	if let Some(pyramid) = pyramid {
+		println!("Refining best candidates...");
+		let fine_image = create_sample_image(base_image, pyramid.fine_width, pyramid.fine_height, card_grid.cards_wide, card_grid.cards_tall);
+		let fine_image = match_brightness(&fine_image, &brightness_map);
+		let fine_alpha = create_alpha_image(base_image, pyramid.fine_width, pyramid.fine_height, card_grid.cards_wide, card_grid.cards_tall);
+		let fine_tile_samples = create_tile_samples(&fine_image, fine_alpha.as_ref(), card_grid, pyramid.fine_width, pyramid.fine_height);
+		let fine_cards = create_card_samples(card_images, sample_cache, pyramid.fine_width, pyramid.fine_height, card_aspect, variants);
+
+		refine_columns(
+			&mut columns,
+			&fine_tile_samples,
+			&card_grid.tiles,
+			&fine_cards,
+			variants,
+			card_grid.cards_wide,
+			card_grid.cards_tall,
+			sample_width * sample_height,
+			pyramid,
+		);
+	}
+
 	//println!("Determining visit order...");
 	//let visit_order = create_visit_order(card_grid.cards_wide, card_grid.cards_tall);
 
 	//println!("determining detail...");
-	//let detail_order = create_detail_order(&sample_image, card_grid.cards_wide, card_grid.cards_tall, sample_size);
+	//let detail_order = create_detail_order(&tile_samples, sample_width, sample_height);
 
 	println!("determining best fit...");
 	let best_fit_order = create_best_fit_order(&columns);
 
 	println!("Selecting cards...");
-	rank_selection(&mut card_grid.grid, &mut columns, &best_fit_order);
+	rank_selection(&mut card_grid.grid, &mut card_grid.variants, &mut card_grid.costs, &mut columns, &best_fit_order);
 }
 
 /**
  * returns (optimal cards in each spot, full columns list)
  */
+#[allow(clippy::too_many_arguments)]
 pub fn rank_all_cards(
-	sample_image: &RgbImage,
-	sample_cards: &Vec<RgbImage>,
+	tile_samples: &[TileSample],
+	tiles: &[Tile],
+	sample_cards: &[Vec<RgbImage>],
+	variants: &[Variant],
 	cards_wide: u32,
 	cards_tall: u32,
-	sample_size: u32,
+	sample_width: u32,
+	sample_height: u32,
 ) -> Vec<Vec<ColumnEntry>> {
 	ranks_to_columns(
-		sample_cards.iter().map(|sample_card| rank_card(
-			sample_image,
-			&sample_card,
+		sample_cards.iter().map(|card_variants| rank_card(
+			tile_samples,
+			tiles,
+			card_variants,
+			variants,
 			cards_wide,
 			cards_tall,
-			sample_size
-		)).collect::<Vec<Vec<u32>>>(),
-		cards_wide,
-		cards_tall
+			sample_width,
+			sample_height,
+		)).collect::<Vec<Vec<(u32, Variant)>>>(),
+		tiles.len(),
 	)
 }
 
@@ -114,79 +175,106 @@ pub fn added_focus_cost(
 	y: u32,
 	cards_wide: u32,
 	cards_tall: u32,
-	sample_size: u32,
+	sample_width: u32,
+	sample_height: u32,
 ) -> u32 {
 	(
 		(2.0_f32 * (x as f32 / cards_wide as f32) - 1.0_f32).powi(2) *
 		(2.0_f32 * (y as f32 / cards_tall as f32) - 1.0_f32).powi(2) *
-		(sample_size as f32).powi(2) * 255.0_f32
+		(sample_width * sample_height) as f32 * 255.0_f32
 	).round() as u32
 }
 
+/**
+ * (difference, variant) of whichever of a card's variants fits a tile best
+ */
+pub fn best_variant_dif(
+	tile_sample: &TileSample,
+	card_variants: &[RgbImage],
+	variants: &[Variant],
+	sample_width: u32,
+	sample_height: u32,
+) -> (u32, Variant) {
+	card_variants.iter().zip(variants.iter()).map(|(sample_card, variant)| (
+		card_dif(
+			tile_sample.image.as_bytes(),
+			tile_sample.image.width(),
+			sample_card.as_bytes(),
+			sample_card.width(),
+			&tile_sample.mask,
+			0,
+			0,
+			sample_width,
+			sample_height,
+		),
+		*variant,
+	)).min_by_key(|(difference, _)| *difference).unwrap()
+}
+
+/**
+ * the cell under a tile's center, which is what tiles are judged by for focus
+ */
+fn tile_focus_cell(tile: &Tile, cards_wide: u32, cards_tall: u32) -> (u32, u32) {
+	let (center_x, center_y) = tile_center(tile);
+
+	(
+		(center_x.floor().max(0.0_f32) as u32).min(cards_wide - 1),
+		(center_y.floor().max(0.0_f32) as u32).min(cards_tall - 1),
+	)
+}
+
+/**
+ * each spot gets the difference of the card's best variant there
+ */
+#[allow(clippy::too_many_arguments)]
 pub fn rank_card(
-	sample_image: &RgbImage,
-	sample_card: &RgbImage,
+	tile_samples: &[TileSample],
+	tiles: &[Tile],
+	card_variants: &[RgbImage],
+	variants: &[Variant],
 	cards_wide: u32,
 	cards_tall: u32,
-	sample_size: u32,
-) -> Vec<u32> {
-	let mut ranks = vec![0u32; (cards_wide * cards_tall) as usize];
-
-	let base_bytes = sample_image.as_bytes();
-	let card_bytes = sample_card.as_bytes();
-
-	for j in 0..cards_tall {
-		for i in 0..cards_wide {
-			let difference = card_dif(
-				base_bytes,
-				sample_image.width(),
-				card_bytes,
-				sample_card.width(),
-				i * sample_size,
-				j * sample_size,
-				sample_size,
-			);
-
-			ranks[(j * cards_wide + i) as usize] = difference + added_focus_cost(i, j, cards_wide, cards_tall, sample_size);
-		}
-	}
-
-	ranks
+	sample_width: u32,
+	sample_height: u32,
+) -> Vec<(u32, Variant)> {
+	tile_samples.iter().zip(tiles.iter()).map(|(tile_sample, tile)| {
+		let (difference, variant) = best_variant_dif(tile_sample, card_variants, variants, sample_width, sample_height);
+		let (center_x, center_y) = tile_focus_cell(tile, cards_wide, cards_tall);
+
+		(difference + added_focus_cost(center_x, center_y, cards_wide, cards_tall, sample_width, sample_height), variant)
+	}).collect::<Vec<(u32, Variant)>>()
 }
 
 
 pub fn create_detail_order(
-	sample_image: &RgbImage,
-	cards_wide: u32,
-	cards_tall: u32,
-	sample_size: u32,
+	tile_samples: &[TileSample],
+	sample_width: u32,
+	sample_height: u32,
 ) -> Vec<usize> {
-	let base_bytes = sample_image.as_bytes();
+	let mut sort_list = Vec::with_capacity(tile_samples.len());
 
-	let mut sort_list = Vec::with_capacity((cards_wide * cards_tall) as usize);
-
-	for spot in 0..(cards_wide * cards_tall) {
-		let x = spot % cards_wide;
-		let y = spot / cards_wide;
+	for (spot, tile_sample) in tile_samples.iter().enumerate() {
+		let base_bytes = tile_sample.image.as_bytes();
+		let spot = spot as u32;
 		let mut running_difference = 0;
 
 		/* horizontal running difference */
-		for j in y * sample_size..(y + 1) * sample_size {
-			let mut last_pixel = pixel_at(base_bytes, sample_image.width(), x * sample_size, j);
+		for j in 0..sample_height {
+			let mut last_pixel = pixel_at(base_bytes, sample_width, 0, j);
 
-			for i in x * sample_size + 1..(x + 1) * sample_size {
-				let this_pixel = pixel_at(base_bytes, sample_image.width(), i, j);
+			for i in 1..sample_width {
+				let this_pixel = pixel_at(base_bytes, sample_width, i, j);
 				running_difference += pixel_difference(last_pixel, this_pixel);
 				last_pixel = this_pixel;
 			}
 		}
 
 		/* vertical running difference */
-		for i in x * sample_size..(x + 1) * sample_size {
-			let mut last_pixel = pixel_at(base_bytes, sample_image.width(), i, y * sample_size);
+		for i in 0..sample_width {
+			let mut last_pixel = pixel_at(base_bytes, sample_width, i, 0);
 
-			for j in y * sample_size + 1..(y + 1) * sample_size {
-				let this_pixel = pixel_at(base_bytes, sample_image.width(), i, j);
+			for j in 1..sample_height {
+				let this_pixel = pixel_at(base_bytes, sample_width, i, j);
 				running_difference += pixel_difference(last_pixel, this_pixel);
 				last_pixel = this_pixel;
 			}
@@ -225,22 +313,23 @@ fn create_best_fit_order(
 pub struct ColumnEntry {
 	difference: u32,
 	id: u32,
+	variant: Variant,
 }
 
 pub fn ranks_to_columns(
-	ranks: Vec<Vec<u32>>,
-	cards_wide: u32,
-	cards_tall: u32,
+	ranks: Vec<Vec<(u32, Variant)>>,
+	num_spaces: usize,
 ) -> Vec<Vec<ColumnEntry>> {
 	let num_cards = ranks.len() as u32;
 
-	let mut columns = Vec::with_capacity((cards_wide * cards_tall) as usize);
+	let mut columns = Vec::with_capacity(num_spaces);
 
-	for space in 0..(cards_wide * cards_tall) {
+	for space in 0..num_spaces {
 		let mut column = Vec::with_capacity(num_cards as usize);
 
 		for card_index in 0..num_cards {
-			column.push(ColumnEntry { difference: ranks[card_index as usize][space as usize], id: card_index });
+			let (difference, variant) = ranks[card_index as usize][space as usize];
+			column.push(ColumnEntry { difference, id: card_index, variant });
 		}
 
 		/* sort by highest differences first (optimal will be last) */
@@ -251,6 +340,52 @@ pub fn ranks_to_columns(
 	columns
 }
 
+/**
+ * rescores the best candidates of every column with fine samples
+ * everything else keeps its coarse order, pushed behind the worst candidate
+ */
+#[allow(clippy::too_many_arguments)]
+fn refine_columns(
+	columns: &mut [Vec<ColumnEntry>],
+	fine_tile_samples: &[TileSample],
+	tiles: &[Tile],
+	fine_cards: &[Vec<RgbImage>],
+	variants: &[Variant],
+	cards_wide: u32,
+	cards_tall: u32,
+	coarse_pixels: u32,
+	pyramid: Pyramid,
+) {
+	let scale = (pyramid.fine_width * pyramid.fine_height) as f32 / coarse_pixels as f32;
+
+	for ((column, tile_sample), tile) in columns.iter_mut().zip(fine_tile_samples.iter()).zip(tiles.iter()) {
+		let (center_x, center_y) = tile_focus_cell(tile, cards_wide, cards_tall);
+		let focus_cost = added_focus_cost(center_x, center_y, cards_wide, cards_tall, pyramid.fine_width, pyramid.fine_height);
+
+		let first_candidate = column.len().saturating_sub(pyramid.candidates);
+
+		let mut worst_candidate = 0;
+		for entry in column[first_candidate..].iter_mut() {
+			let (difference, variant) = best_variant_dif(tile_sample, &fine_cards[entry.id as usize], variants, pyramid.fine_width, pyramid.fine_height);
+			entry.difference = difference + focus_cost;
+			entry.variant = variant;
+			worst_candidate = worst_candidate.max(entry.difference);
+		}
+
+		if first_candidate > 0 {
+			let best_rest = (column[first_candidate - 1].difference as f32 * scale).round() as u32;
+			let shift = worst_candidate.saturating_sub(best_rest);
+
+			for entry in column[..first_candidate].iter_mut() {
+				entry.difference = (entry.difference as f32 * scale).round() as u32 + shift;
+			}
+		}
+
+		/* sort by highest differences first (optimal will be last) */
+		column.sort_unstable_by(|column0, column1| column0.difference.partial_cmp(&column1.difference).unwrap().reverse());
+	}
+}
+
 pub fn create_select_grid(
 	cards_wide: u32,
 	cards_tall: u32,
@@ -355,15 +490,22 @@ fn create_visit_order(
  * first, insert each card at least once
  */
 pub fn rank_selection(
-	card_grid: &mut Vec<u32>,
-	columns: &mut Vec<Vec<ColumnEntry>>,
-	visit_order: &Vec<usize>,
+	card_grid: &mut [u32],
+	variants: &mut [Variant],
+	costs: &mut [u32],
+	columns: &mut [Vec<ColumnEntry>],
+	visit_order: &[usize],
 ) {
+	/* a shape can leave no tiles at all */
+	if columns.is_empty() { return; }
+
 	let num_cards = columns[0].len();
 
 	/* place every unique card */
 	sub_rank_selection(
 		card_grid,
+		variants,
+		costs,
 		columns,
 		visit_order,
 		0,
@@ -373,6 +515,8 @@ pub fn rank_selection(
 	/* place remaining duplicates */
 	sub_rank_selection(
 		card_grid,
+		variants,
+		costs,
 		columns,
 		visit_order,
 		num_cards,
@@ -381,9 +525,11 @@ pub fn rank_selection(
 }
 
 fn sub_rank_selection(
-	card_grid: &mut Vec<u32>,
-	columns: &mut Vec<Vec<ColumnEntry>>,
-	visit_order: &Vec<usize>,
+	card_grid: &mut [u32],
+	variants: &mut [Variant],
+	costs: &mut [u32],
+	columns: &mut [Vec<ColumnEntry>],
+	visit_order: &[usize],
 	start_index: usize,
 	end_index: usize,
 ) {
@@ -395,6 +541,8 @@ fn sub_rank_selection(
 		/* risky, must guarantee that the column has not run out */
 		let card_id = best_column.last().unwrap().id;
 		card_grid[best_index] = card_id;
+		variants[best_index] = best_column.last().unwrap().variant;
+		costs[best_index] = best_column.last().unwrap().difference;
 
 		/* delete all of that card's entries in the future columns */
 		/* leave the overflow duplicate untouched */
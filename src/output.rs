@@ -0,0 +1,216 @@
+use image::ColorType;
+use image::codecs::jpeg::JpegEncoder;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use crate::deepzoom::{PyramidKind, save_pyramid};
+
+/* 1 is the slowest and smallest, 10 the fastest */
+const AVIF_SPEED: u8 = 6;
+const INCHES_PER_METER: f32 = 39.3701_f32;
+
+/**
+ * file formats the final image can be saved in
+ */
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+	Png,
+	Jpeg,
+	WebP,
+	Tiff,
+	Avif,
+	/* tile pyramids for zooming viewers, see deepzoom */
+	DeepZoom,
+	Iiif,
+}
+
+/**
+ * quality is used by jpeg, lossy webp and avif, from 1 to 100
+ * lossless only affects webp, the other formats are always one or the other
+ * dpi is written into png and tiff files
+ * alpha means the strips are rgba, only png, webp and tiff can keep it
+ */
+#[derive(Clone, Copy)]
+pub struct OutputOptions {
+	pub format: OutputFormat,
+	pub quality: u8,
+	pub lossless: bool,
+	pub dpi: Option<u32>,
+	pub alpha: bool,
+}
+
+/**
+ * works for both --format names and file extensions
+ */
+pub fn parse_output_format(name: &str) -> Option<OutputFormat> {
+	match name.to_lowercase().as_str() {
+		"png" => Some(OutputFormat::Png),
+		"jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+		"webp" => Some(OutputFormat::WebP),
+		"tif" | "tiff" => Some(OutputFormat::Tiff),
+		"avif" => Some(OutputFormat::Avif),
+		"dzi" => Some(OutputFormat::DeepZoom),
+		"iiif" => Some(OutputFormat::Iiif),
+		_ => None,
+	}
+}
+
+pub fn keeps_alpha(format: OutputFormat) -> bool {
+	[OutputFormat::Png, OutputFormat::WebP, OutputFormat::Tiff].contains(&format)
+}
+
+pub fn output_format_extension(format: OutputFormat) -> &'static str {
+	match format {
+		OutputFormat::Png => "png",
+		OutputFormat::Jpeg => "jpg",
+		OutputFormat::WebP => "webp",
+		OutputFormat::Tiff => "tiff",
+		OutputFormat::Avif => "avif",
+		OutputFormat::DeepZoom => "dzi",
+		/* iiif is a folder */
+		OutputFormat::Iiif => "",
+	}
+}
+
+/**
+ * where to save and in what format
+ * a format given without a path swaps the default path's extension,
+ * otherwise the format comes from the path's extension
+ */
+pub fn output_target(path: Option<&str>, format: Option<OutputFormat>, default_path: &str) -> (PathBuf, OutputFormat) {
+	match (path, format) {
+		(Some(path), Some(format)) => (PathBuf::from(path), format),
+		(Some(path), None) => {
+			let format = Path::new(path).extension()
+				.and_then(|extension| parse_output_format(&extension.to_string_lossy()))
+				.expect("Unknown output extension, pass --format!");
+			(PathBuf::from(path), format)
+		},
+		(None, Some(format)) => (Path::new(default_path).with_extension(output_format_extension(format)), format),
+		(None, None) => (PathBuf::from(default_path), OutputFormat::Png),
+	}
+}
+
+/**
+ * draw_strip is asked for the rows between two heights, top to bottom
+ * png, tiff and the tile pyramids are written a strip at a time as they come in,
+ * the other encoders need the whole image so their strips are gathered first
+ */
+pub fn save_output(
+	path: &Path,
+	options: OutputOptions,
+	width: u32,
+	height: u32,
+	strip_height: u32,
+	draw_strip: &mut dyn FnMut(u32, u32) -> Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+	if options.alpha && !keeps_alpha(options.format) {
+		return Err("Alpha output needs png, webp or tiff!".into());
+	}
+
+	match options.format {
+		OutputFormat::DeepZoom => return Ok(save_pyramid(PyramidKind::DeepZoom, path, options.quality, width, height, strip_height, draw_strip)?),
+		OutputFormat::Iiif => return Ok(save_pyramid(PyramidKind::Iiif, path, options.quality, width, height, strip_height, draw_strip)?),
+		_ => {},
+	}
+
+	let strips = (0..height).step_by(strip_height as usize).map(|from_y| (from_y, (from_y + strip_height).min(height)));
+
+	let mut writer = BufWriter::new(File::create(path)?);
+
+	match options.format {
+		OutputFormat::Png => {
+			let mut encoder = png::Encoder::new(&mut writer, width, height);
+			encoder.set_color(if options.alpha { png::ColorType::RGBA } else { png::ColorType::RGB });
+			encoder.set_depth(png::BitDepth::Eight);
+
+			let mut png_writer = encoder.write_header()?;
+
+			if let Some(dpi) = options.dpi {
+				/* pHYs is pixels per meter for x and y, then 1 meaning meters */
+				let pixels_per_meter = (dpi as f32 * INCHES_PER_METER).round() as u32;
+
+				let mut physical = Vec::with_capacity(9);
+				physical.extend_from_slice(&pixels_per_meter.to_be_bytes());
+				physical.extend_from_slice(&pixels_per_meter.to_be_bytes());
+				physical.push(1);
+
+				png_writer.write_chunk(*b"pHYs", &physical)?;
+			}
+
+			let mut stream_writer = png_writer.stream_writer();
+
+			for (from_y, to_y) in strips {
+				stream_writer.write_all(&draw_strip(from_y, to_y))?;
+			}
+
+			stream_writer.finish()?;
+		},
+		OutputFormat::Tiff => {
+			let mut encoder = tiff::encoder::TiffEncoder::new(&mut writer)?;
+			if options.alpha {
+				write_tiff::<tiff::encoder::colortype::RGBA8, _>(&mut encoder, options.dpi, width, height, strip_height, strips, draw_strip)?;
+			} else {
+				write_tiff::<tiff::encoder::colortype::RGB8, _>(&mut encoder, options.dpi, width, height, strip_height, strips, draw_strip)?;
+			}
+		},
+		format => {
+			/* big prints don't fit in a u32 */
+			let mut bytes = Vec::with_capacity(width as usize * height as usize * if options.alpha { 4 } else { 3 });
+
+			for (from_y, to_y) in strips {
+				bytes.extend_from_slice(&draw_strip(from_y, to_y));
+			}
+
+			match format {
+				OutputFormat::Jpeg => JpegEncoder::new_with_quality(&mut writer, options.quality).encode(&bytes, width, height, ColorType::Rgb8)?,
+				OutputFormat::WebP => {
+					let encoder = if options.alpha { webp::Encoder::from_rgba(&bytes, width, height) } else { webp::Encoder::from_rgb(&bytes, width, height) };
+					let encoded = if options.lossless { encoder.encode_lossless() } else { encoder.encode(options.quality as f32) };
+					writer.write_all(&encoded)?;
+				},
+				OutputFormat::Avif => {
+					let pixels = bytes.chunks(3).map(|pixel| ravif::RGB8::new(pixel[0], pixel[1], pixel[2])).collect::<Vec<ravif::RGB8>>();
+					let encoded = ravif::Encoder::new()
+						.with_quality(options.quality.max(1) as f32)
+						.with_speed(AVIF_SPEED)
+						.encode_rgb(ravif::Img::new(pixels.as_slice(), width as usize, height as usize))?;
+					writer.write_all(&encoded.avif_file)?;
+				},
+				_ => unreachable!(),
+			}
+		},
+	}
+
+	writer.flush()?;
+
+	Ok(())
+}
+
+/**
+ * tiff images are typed by their color, so rgb and rgba each get their own
+ */
+fn write_tiff<C: tiff::encoder::colortype::ColorType<Inner = u8>, W: Write + std::io::Seek>(
+	encoder: &mut tiff::encoder::TiffEncoder<W>,
+	dpi: Option<u32>,
+	width: u32,
+	height: u32,
+	strip_height: u32,
+	strips: impl Iterator<Item = (u32, u32)>,
+	draw_strip: &mut dyn FnMut(u32, u32) -> Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+	let mut tiff_image = encoder.new_image::<C>(width, height)?;
+	tiff_image.rows_per_strip(strip_height)?;
+
+	if let Some(dpi) = dpi {
+		tiff_image.resolution(tiff::tags::ResolutionUnit::Inch, tiff::encoder::Rational { n: dpi, d: 1 });
+	}
+
+	for (from_y, to_y) in strips {
+		tiff_image.write_strip(&draw_strip(from_y, to_y))?;
+	}
+
+	tiff_image.finish()?;
+
+	Ok(())
+}
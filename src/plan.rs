@@ -0,0 +1,139 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use crate::manifest::{Manifest, ManifestCell};
+use crate::metadata::{CardMetadata, card_metadata};
+use crate::transform::{Transform, parse_transform};
+
+/* cells are grouped into rows by their top edge, in quarters of a cell so hex rows stay apart */
+const ROW_STEPS: f32 = 4.0_f32;
+
+const PLAN_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Mosaic plan</title>
+<style>
+	body { font-family: sans-serif; font-size: 11pt; }
+	table { border-collapse: collapse; margin-bottom: 1em; }
+	th, td { border: 1px solid #999; padding: 0.2em 0.5em; text-align: left; }
+	td.count { text-align: right; }
+	h2 { page-break-before: always; }
+	h2:first-of-type { page-break-before: auto; }
+	h3 { page-break-after: avoid; }
+	table { page-break-inside: auto; }
+	tr { page-break-inside: avoid; }
+</style>
+</head>
+<body>
+<h1>Mosaic plan</h1>
+<p>{{SUMMARY}}</p>
+<h2>Pick list</h2>
+<table>
+<tr><th>Set</th><th>Number</th><th>Card</th><th>Count</th></tr>
+{{PICKS}}
+</table>
+<h2>Placement</h2>
+<p>Rows run top to bottom and cards left to right, sizes are in cards.
+Window is where along its spare length the card's visible part sits, 0 is the left or top edge and 1 the right or bottom edge.</p>
+{{ROWS}}
+</body>
+</html>
+"#;
+
+fn escape_html(text: &str) -> String {
+	text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/**
+ * the card as a volunteer would look for it, files without metadata go by name
+ */
+fn card_label(cell: &ManifestCell, metadata: &HashMap<String, CardMetadata>) -> (String, String, String) {
+	match card_metadata(metadata, Path::new(&cell.file)) {
+		Some(card) => (card.set.to_uppercase(), card.collector_number.clone(), card.name.clone()),
+		None => (String::new(), String::new(), Path::new(&cell.file).file_name().unwrap().to_string_lossy().into_owned()),
+	}
+}
+
+/**
+ * how to lay a card down for a transform, mirrored cards can't be built from real ones
+ */
+fn orientation(transform: Transform) -> &'static str {
+	match transform {
+		Transform::Original => "upright",
+		Transform::Rotate180 => "upside down",
+		Transform::FlipHorizontal => "mirrored",
+		Transform::FlipVertical => "mirrored, upside down",
+	}
+}
+
+fn is_mirrored(cell: &ManifestCell) -> bool {
+	matches!(parse_transform(&cell.transform), Some(Transform::FlipHorizontal) | Some(Transform::FlipVertical))
+}
+
+/**
+ * collector numbers sort by their number first, so 9 comes before 10 and 10a after 10
+ */
+fn collector_order(collector_number: &str) -> (u32, String) {
+	let digits = collector_number.chars().take_while(|character| character.is_ascii_digit()).collect::<String>();
+	(digits.parse::<u32>().unwrap_or(u32::MAX), collector_number.to_string())
+}
+
+/**
+ * a printable page for building the mosaic out of real cards
+ * a pick list of every card with how many are needed, then every row's cards in order
+ */
+pub fn write_plan(path: &Path, manifest: &Manifest, metadata: &HashMap<String, CardMetadata>) -> std::io::Result<()> {
+	/* (set, collector order, name) -> count */
+	let mut picks: BTreeMap<(String, (u32, String), String), u32> = BTreeMap::new();
+
+	for cell in &manifest.cells {
+		let (set, collector_number, name) = card_label(cell, metadata);
+		*picks.entry((set, collector_order(&collector_number), name)).or_insert(0) += 1;
+	}
+
+	let pick_rows = picks.iter().map(|((set, (_, collector_number), name), count)| format!(
+		"<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"count\">{}</td></tr>",
+		escape_html(set), escape_html(collector_number), escape_html(name), count,
+	)).collect::<Vec<String>>().join("\n");
+
+	/* row key -> cells in that row */
+	let mut rows: BTreeMap<i32, Vec<&ManifestCell>> = BTreeMap::new();
+
+	for cell in &manifest.cells {
+		rows.entry((cell.y * ROW_STEPS).round() as i32).or_default().push(cell);
+	}
+
+	let placement = rows.values_mut().enumerate().map(|(row_index, cells)| {
+		cells.sort_by(|cell0, cell1| cell0.x.partial_cmp(&cell1.x).unwrap());
+
+		let cell_rows = cells.iter().enumerate().map(|(position, cell)| {
+			let (set, collector_number, name) = card_label(cell, metadata);
+			format!(
+				"<tr><td class=\"count\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{} x {}</td><td>{}</td><td>{:.2}</td></tr>",
+				position + 1, escape_html(&name), escape_html(&set), escape_html(&collector_number), cell.width, cell.height,
+				parse_transform(&cell.transform).map_or("unknown", orientation), cell.window,
+			)
+		}).collect::<Vec<String>>().join("\n");
+
+		format!(
+			"<h3>Row {}</h3>\n<table>\n<tr><th>#</th><th>Card</th><th>Set</th><th>Number</th><th>Size</th><th>Orientation</th><th>Window</th></tr>\n{}\n</table>",
+			row_index + 1, cell_rows,
+		)
+	}).collect::<Vec<String>>().join("\n");
+
+	let mut summary = format!(
+		"{} cards in {} rows, {} different, on a {} grid {} cards wide and {} tall.",
+		manifest.cells.len(), rows.len(), picks.len(), escape_html(&manifest.layout), manifest.cards_wide, manifest.cards_tall,
+	);
+
+	let mirrored = manifest.cells.iter().filter(|cell| is_mirrored(cell)).count();
+	if mirrored > 0 {
+		summary.push_str(&format!(" {} cards are mirrored, which real cards can't be, make the mosaic without --augment mirror or rotate to build it.", mirrored));
+	}
+
+	fs::write(path, PLAN_PAGE
+		.replace("{{SUMMARY}}", &summary)
+		.replace("{{PICKS}}", &pick_rows)
+		.replace("{{ROWS}}", &placement))
+}
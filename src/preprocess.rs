@@ -1,7 +1,9 @@
-use image::{DynamicImage, EncodableLayout, RgbImage};
+use image::{EncodableLayout, GrayImage, RgbImage};
 use tokio::macros::support::thread_rng_n;
 use rand::thread_rng;
 use rand::seq::SliceRandom;
+use crate::library::LibraryCard;
+use crate::alpha::ALPHA_THRESHOLD;
 
 pub fn create_brightness_counts() -> Vec<u32> {
 	vec![0_u32; 256]
@@ -29,13 +31,19 @@ fn pixel_at(
 	)
 }
 
+/**
+ * pixels the alpha image has as transparent aren't counted, they're never matched against
+ */
 pub fn count_brightness(
 	image: &RgbImage,
+	alpha_image: Option<&GrayImage>,
 	counts: &mut Vec<u32>,
 ) {
 	let image_bytes = image.as_bytes();
 
 	for i in 0..image.width() * image.height() {
+		if alpha_image.is_some_and(|alpha_image| alpha_image.as_bytes()[i as usize] < ALPHA_THRESHOLD) { continue; }
+
 		let brightness = brightness_at(image_bytes, i as usize);
 		counts[brightness.round() as usize] += 1;
 	}
@@ -97,7 +105,7 @@ pub fn match_brightness(
 }
 
 pub fn add_duplicates(
-	card_images: &mut Vec<DynamicImage>,
+	card_images: &mut Vec<LibraryCard>,
 	num_duplicates: u32,
 ) {
 	card_images.shuffle(&mut thread_rng());
@@ -0,0 +1,139 @@
+use crate::CardGrid;
+use crate::library::LibraryCard;
+use crate::layout::tile_bounds;
+use crate::window::window_rect;
+
+const CM_PER_INCH: f32 = 2.54_f32;
+
+/**
+ * physical size of the print, in inches
+ */
+#[derive(Clone, Copy)]
+pub struct PrintSize {
+	pub width: f32,
+	pub height: f32,
+}
+
+/**
+ * "24x36" in inches, or "60x90cm"
+ */
+pub fn parse_print_size(text: &str) -> Option<PrintSize> {
+	let (text, scale) = match text.strip_suffix("cm") {
+		Some(text) => (text, 1.0_f32 / CM_PER_INCH),
+		None => (text.strip_suffix("in").unwrap_or(text), 1.0_f32),
+	};
+
+	let mut sides = text.split('x').map(|side| side.trim().parse::<f32>().ok());
+	let width = sides.next()??;
+	let height = sides.next()??;
+
+	if sides.next().is_some() || width <= 0.0_f32 || height <= 0.0_f32 { return None; }
+
+	Some(PrintSize { width: width * scale, height: height * scale })
+}
+
+/**
+ * pixel width of the output so the grid fits inside the print at dpi
+ * the grid keeps its own aspect, so one side may come out short of the print
+ */
+fn print_image_width(print_size: PrintSize, dpi: u32, card_grid: &CardGrid, card_aspect: f32) -> u32 {
+	let grid_aspect = (card_grid.cards_wide as f32 * card_aspect) / card_grid.cards_tall as f32;
+
+	let width = (print_size.width * dpi as f32).min(print_size.height * dpi as f32 * grid_aspect);
+
+	width.round() as u32
+}
+
+/**
+ * IMAGE_WIDTH unless a print size was asked for
+ */
+pub fn output_image_width(print_size: Option<PrintSize>, dpi: u32, card_grid: &CardGrid, card_aspect: f32, default_width: u32) -> u32 {
+	let print_size = match print_size {
+		Some(print_size) => print_size,
+		None => return default_width,
+	};
+
+	let image_width = print_image_width(print_size, dpi, card_grid, card_aspect);
+	let card_width = image_width as f32 / card_grid.cards_wide as f32;
+	let image_height = (card_width / card_aspect * card_grid.cards_tall as f32).round() as u32;
+
+	println!(
+		"Printing {:.1} x {:.1} in at {} dpi, {} x {} pixels, {} pixels per card",
+		image_width as f32 / dpi as f32,
+		image_height as f32 / dpi as f32,
+		dpi,
+		image_width,
+		image_height,
+		card_width.round(),
+	);
+
+	image_width
+}
+
+/**
+ * counts the tiles that will be drawn bigger than the part of their card they show
+ */
+pub fn warn_upscaled(card_grid: &CardGrid, card_images: &[LibraryCard], card_aspect: f32, image_width: u32) {
+	let card_width = image_width as f32 / card_grid.cards_wide as f32;
+
+	/* only the header of each card is read, and only once per card */
+	let mut source_widths: Vec<Option<f32>> = vec![None; card_images.len()];
+
+	let mut upscaled = 0u32;
+	let mut worst_scale = 1.0_f32;
+
+	for (tile_index, tile) in card_grid.tiles.iter().enumerate() {
+		let card_index = card_grid.grid[tile_index] as usize;
+
+		let source_width = *source_widths[card_index].get_or_insert_with(|| {
+			let (width, height) = image::image_dimensions(&card_images[card_index].path).unwrap_or((0, 0));
+			let (_, _, window_width, _) = window_rect(width as f32, height as f32, card_aspect, card_grid.variants[tile_index].window);
+			window_width
+		});
+
+		let (min_x, _, max_x, _) = tile_bounds(tile);
+		let scale = (max_x - min_x) * card_width / source_width;
+
+		if scale > 1.0_f32 {
+			upscaled += 1;
+			worst_scale = worst_scale.max(scale);
+		}
+	}
+
+	if upscaled > 0 {
+		println!(
+			"Warning: {} of {} tiles are drawn larger than their source, up to {:.1}x, lower the size or dpi to keep them sharp",
+			upscaled,
+			card_grid.tiles.len(),
+			worst_scale,
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn close(size: PrintSize, width: f32, height: f32) -> bool {
+		(size.width - width).abs() < 0.001_f32 && (size.height - height).abs() < 0.001_f32
+	}
+
+	#[test]
+	fn inches_by_default() {
+		assert!(close(parse_print_size("24x36").unwrap(), 24_f32, 36_f32));
+		assert!(close(parse_print_size("24x36in").unwrap(), 24_f32, 36_f32));
+		assert!(close(parse_print_size("8.5 x 11").unwrap(), 8.5_f32, 11_f32));
+	}
+
+	#[test]
+	fn centimeters_become_inches() {
+		assert!(close(parse_print_size("60x90cm").unwrap(), 60_f32 / CM_PER_INCH, 90_f32 / CM_PER_INCH));
+	}
+
+	#[test]
+	fn bad_sizes() {
+		for text in ["", "24", "24x", "x36", "24x36x48", "0x36", "-24x36", "24by36", "24x36mm"].iter() {
+			assert!(parse_print_size(text).is_none(), "{} parsed", text);
+		}
+	}
+}
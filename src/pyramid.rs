@@ -0,0 +1,39 @@
+use image::RgbImage;
+use crate::layout::TileSample;
+use crate::new_sample::best_variant_dif;
+use crate::window::Variant;
+
+/**
+ * coarse to fine matching
+ * every card is first scored with small samples,
+ * then only the best candidates of each tile are scored again with fine samples
+ */
+#[derive(Clone, Copy)]
+pub struct Pyramid {
+	pub fine_width: u32,
+	pub fine_height: u32,
+	pub candidates: usize,
+}
+
+/**
+ * indices of the cards out of available that best fit a tile at coarse resolution, best first
+ */
+pub fn coarse_candidates(
+	tile_sample: &TileSample,
+	card_samples: &[Vec<RgbImage>],
+	available: &[u32],
+	variants: &[Variant],
+	sample_width: u32,
+	sample_height: u32,
+	num_candidates: usize,
+) -> Vec<u32> {
+	let mut scores = available.iter().map(|&card_index| {
+		let (difference, _) = best_variant_dif(tile_sample, &card_samples[card_index as usize], variants, sample_width, sample_height);
+		(difference, card_index)
+	}).collect::<Vec<(u32, u32)>>();
+
+	scores.sort_unstable_by_key(|(difference, _)| *difference);
+	scores.truncate(num_candidates);
+
+	scores.iter().map(|(_, card_index)| *card_index).collect::<Vec<u32>>()
+}
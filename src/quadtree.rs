@@ -0,0 +1,122 @@
+use image::{DynamicImage, RgbImage, EncodableLayout};
+use crate::{CardGrid, Tile, create_sample_image};
+use crate::layout::{Layout, square_tile};
+use crate::window::CENTER_VARIANT;
+
+/**
+ * average deviation of every pixel in a block from the block's mean color
+ * flat regions are close to 0, busy regions are high
+ */
+pub fn block_detail(
+	sample_image: &RgbImage,
+	min_x: u32,
+	min_y: u32,
+	block_width: u32,
+	block_height: u32,
+) -> f32 {
+	let bytes = sample_image.as_bytes();
+	let width = sample_image.width();
+	let num_pixels = (block_width * block_height) as f32;
+
+	let mut mean = [0_f32; 3];
+	for j in min_y..min_y + block_height {
+		for i in min_x..min_x + block_width {
+			let index = ((j * width + i) * 3) as usize;
+			for channel in 0..3 {
+				mean[channel] += bytes[index + channel] as f32;
+			}
+		}
+	}
+	for channel in mean.iter_mut() {
+		*channel /= num_pixels;
+	}
+
+	let mut deviation = 0_f32;
+	for j in min_y..min_y + block_height {
+		for i in min_x..min_x + block_width {
+			let index = ((j * width + i) * 3) as usize;
+			for channel in 0..3 {
+				deviation += (bytes[index + channel] as f32 - mean[channel]).abs();
+			}
+		}
+	}
+
+	deviation / num_pixels
+}
+
+/**
+ * replaces the uniform tiles of the grid with merged tiles
+ * blocks of max_span x max_span cells are merged when their detail is under the threshold,
+ * otherwise they are split into four and checked again, down to single cells
+ */
+pub fn subdivide_grid(
+	base_image: &DynamicImage,
+	card_grid: &mut CardGrid,
+	sample_width: u32,
+	sample_height: u32,
+	max_span: u32,
+	detail_threshold: f32,
+) {
+	if max_span <= 1 { return; }
+
+	/* merging only makes sense on a square lattice */
+	if card_grid.layout != Layout::Square {
+		println!("Not merging tiles, --max-span only works with the square layout");
+		return;
+	}
+
+	let sample_image = create_sample_image(base_image, sample_width, sample_height, card_grid.cards_wide, card_grid.cards_tall);
+	let subdivision = Subdivision {
+		sample_image: &sample_image,
+		cards_wide: card_grid.cards_wide,
+		cards_tall: card_grid.cards_tall,
+		sample_width,
+		sample_height,
+		detail_threshold,
+	};
+
+	let mut tiles = Vec::with_capacity(card_grid.tiles.len());
+
+	for y in (0..card_grid.cards_tall).step_by(max_span as usize) {
+		for x in (0..card_grid.cards_wide).step_by(max_span as usize) {
+			subdivide_block(&subdivision, x, y, max_span, &mut tiles);
+		}
+	}
+
+	card_grid.grid = vec![0u32; tiles.len()];
+	card_grid.variants = vec![CENTER_VARIANT; tiles.len()];
+	card_grid.costs = vec![0u32; tiles.len()];
+	card_grid.tiles = tiles;
+}
+
+/**
+ * what every block of the grid is checked against
+ */
+struct Subdivision<'a> {
+	sample_image: &'a RgbImage,
+	cards_wide: u32,
+	cards_tall: u32,
+	sample_width: u32,
+	sample_height: u32,
+	detail_threshold: f32,
+}
+
+fn subdivide_block(subdivision: &Subdivision, x: u32, y: u32, span: u32, tiles: &mut Vec<Tile>) {
+	let Subdivision { sample_image, cards_wide, cards_tall, sample_width, sample_height, detail_threshold } = *subdivision;
+
+	if x >= cards_wide || y >= cards_tall { return; }
+
+	let fits = x + span <= cards_wide && y + span <= cards_tall;
+
+	if span == 1 || (fits && block_detail(sample_image, x * sample_width, y * sample_height, span * sample_width, span * sample_height) <= detail_threshold) {
+		tiles.push(square_tile(x, y, span));
+
+	} else {
+		let half = span / 2;
+
+		subdivide_block(subdivision, x, y, half, tiles);
+		subdivide_block(subdivision, x + half, y, half, tiles);
+		subdivide_block(subdivision, x, y + half, half, tiles);
+		subdivide_block(subdivision, x + half, y + half, half, tiles);
+	}
+}
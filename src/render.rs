@@ -0,0 +1,253 @@
+use image::{DynamicImage, RgbaImage, EncodableLayout};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use crate::CardGrid;
+use crate::library::{LibraryCard, load_card_image};
+use crate::layout::{tile_bounds, tile_contains, tile_edge_distance};
+use crate::window::crop_window;
+use crate::transform::transform_point;
+use crate::image_kind::outside_corner;
+use crate::kernel::{Kernel, resize_with_kernel};
+
+/* rows drawn at a time, memory use grows with this times the image width */
+pub const STRIP_HEIGHT: u32 = 256;
+
+/**
+ * how tiles are set apart, sizes are in pixels of the output
+ * grout is the gap between neighboring tiles, border a stroke just inside each card,
+ * corner_radius rounds off each card's rectangle, as a fraction of its width
+ * background fills wherever no card or grout is drawn, like empty tiles
+ * colors that are none are left transparent, which only shows in alpha output
+ */
+#[derive(Clone, Copy)]
+pub struct TileStyle {
+	pub grout: f32,
+	pub grout_color: Option<[u8; 3]>,
+	pub border: f32,
+	pub border_color: [u8; 3],
+	pub corner_radius: f32,
+	pub background: Option<[u8; 3]>,
+}
+
+/* (card file, window bits, width, height) of a card resized for a tile */
+type TileImageKey = (PathBuf, u32, u32, u32);
+
+/**
+ * draws the mosaic a strip of rows at a time, top to bottom
+ * each card is resized once, straight from the file to the size of the tile it's drawn in
+ * files are loaded when the first strip showing them is drawn and dropped once every tile using them is resized,
+ * resized cards are kept until the last strip showing them,
+ * so only the cards around the current strip are ever in memory
+ */
+pub struct StripRenderer<'a> {
+	card_grid: &'a CardGrid,
+	card_images: &'a Vec<LibraryCard>,
+	card_aspect: f32,
+	style: TileStyle,
+	kernel: Kernel,
+	/* rgba rows instead of rgb */
+	alpha: bool,
+	pub image_width: u32,
+	pub image_height: u32,
+	card_width: f32,
+	card_height: f32,
+	/* see tile_pixel_bounds */
+	tile_pixels: Vec<(i32, i32, i32, i32)>,
+	/* the first row of the last tile each card file is in */
+	last_starts: HashMap<&'a Path, i32>,
+	/* the last row each card file is drawn on */
+	last_rows: HashMap<&'a Path, i32>,
+	loaded_cards: HashMap<PathBuf, DynamicImage>,
+	tile_images: HashMap<TileImageKey, RgbaImage>,
+}
+
+/**
+ * (card width, card height, image height) in pixels for an image image_width wide
+ */
+pub fn render_dimensions(card_grid: &CardGrid, card_aspect: f32, image_width: u32) -> (f32, f32, u32) {
+	let card_width = image_width as f32 / card_grid.cards_wide as f32;
+	let card_height = (1f32 / card_aspect) * card_width;
+
+	(card_width, card_height, (card_height * card_grid.cards_tall as f32).round() as u32)
+}
+
+/**
+ * (min x, min y, max x, max y) of every tile in pixels, including parts off the image
+ */
+pub fn tile_pixel_bounds(card_grid: &CardGrid, card_aspect: f32, image_width: u32) -> Vec<(i32, i32, i32, i32)> {
+	let (card_width, card_height, _) = render_dimensions(card_grid, card_aspect, image_width);
+
+	card_grid.tiles.iter().map(|tile| {
+		let (min_x, min_y, max_x, max_y) = tile_bounds(tile);
+		(
+			(min_x * card_width).round() as i32,
+			(min_y * card_height).round() as i32,
+			(max_x * card_width).round() as i32,
+			(max_y * card_height).round() as i32,
+		)
+	}).collect::<Vec<(i32, i32, i32, i32)>>()
+}
+
+pub fn create_renderer<'a>(card_grid: &'a CardGrid, card_images: &'a Vec<LibraryCard>, card_aspect: f32, image_width: u32, style: TileStyle, kernel: Kernel, alpha: bool) -> StripRenderer<'a> {
+	let (card_width, card_height, image_height) = render_dimensions(card_grid, card_aspect, image_width);
+	let tile_pixels = tile_pixel_bounds(card_grid, card_aspect, image_width);
+
+	let mut last_starts: HashMap<&Path, i32> = HashMap::new();
+	let mut last_rows: HashMap<&Path, i32> = HashMap::new();
+
+	for (tile_index, (_, min_y, _, max_y)) in tile_pixels.iter().enumerate() {
+		let path = card_images[card_grid.grid[tile_index] as usize].path.as_path();
+
+		let last_start = last_starts.entry(path).or_insert(*min_y);
+		*last_start = (*last_start).max(*min_y);
+
+		let last_row = last_rows.entry(path).or_insert(*max_y);
+		*last_row = (*last_row).max(*max_y);
+	}
+
+	StripRenderer {
+		card_grid,
+		card_images,
+		card_aspect,
+		style,
+		kernel,
+		alpha,
+		image_width,
+		image_height,
+		card_width,
+		card_height,
+		tile_pixels,
+		last_starts,
+		last_rows,
+		loaded_cards: HashMap::new(),
+		tile_images: HashMap::new(),
+	}
+}
+
+/**
+ * a window of a card resized to exactly the size of the tile it's drawn in
+ */
+fn resize_tile_image(full_image: &DynamicImage, card_aspect: f32, window: f32, width: u32, height: u32, kernel: Kernel) -> RgbaImage {
+	resize_with_kernel(&crop_window(full_image, card_aspect, window).to_rgba8(), width, height, kernel)
+}
+
+/**
+ * rgb or rgba bytes of rows from_y up to to_y
+ * strips have to be drawn in order, cards from earlier strips may already be gone
+ * with grout each card shrinks into its tile by half the grout on every side
+ */
+pub fn draw_strip(renderer: &mut StripRenderer, from_y: u32, to_y: u32) -> Vec<u8> {
+	/* draws a pixel over whatever is already there */
+	fn put_pixel(bytes: &mut [u8], pixel: &[u8; 4], channels: u32, width: u32, x: u32, y: u32) {
+		let index = ((y * width + x) * channels) as usize;
+		let alpha = pixel[3] as u32;
+
+		if alpha == 0 { return; }
+
+		if alpha == 255 {
+			bytes[index..index + channels as usize].copy_from_slice(&pixel[..channels as usize]);
+			return;
+		}
+
+		let under_alpha = if channels == 4 { bytes[index + 3] as u32 * (255 - alpha) / 255 } else { 255 - alpha };
+		let total_alpha = alpha + under_alpha;
+
+		for channel in 0..3 {
+			bytes[index + channel] = ((pixel[channel] as u32 * alpha + bytes[index + channel] as u32 * under_alpha) / total_alpha) as u8;
+		}
+		if channels == 4 { bytes[index + 3] = total_alpha as u8; }
+	}
+
+	fn opaque(color: Option<[u8; 3]>) -> [u8; 4] {
+		color.map_or([0, 0, 0, 0], |color| [color[0], color[1], color[2], 255])
+	}
+
+	let image_width = renderer.image_width;
+	let card_width = renderer.card_width;
+	let card_height = renderer.card_height;
+	let card_grid = renderer.card_grid;
+	let style = renderer.style;
+	let channels = if renderer.alpha { 4 } else { 3 };
+	let (grout_color, border_color) = (opaque(style.grout_color), opaque(Some(style.border_color)));
+
+	let mut draw_bytes = opaque(style.background)[..channels as usize].repeat((image_width * (to_y - from_y)) as usize);
+
+	/* distances to the tile's edges are only needed for gaps and strokes along them */
+	let inset = style.grout / 2_f32;
+	let edged = style.grout > 0_f32 || style.border > 0_f32;
+
+	for (tile_index, tile) in card_grid.tiles.iter().enumerate() {
+		/* the card covers the whole bounding box, even the parts cut off by the image edges */
+		let (min_x, min_y, max_x, max_y) = renderer.tile_pixels[tile_index];
+		if max_y <= from_y as i32 || min_y >= to_y as i32 { continue; }
+
+		/* the part of the bounding box the card is stretched over */
+		let (card_min_x, card_min_y) = (min_x as f32 + inset, min_y as f32 + inset);
+		let x_len = (max_x - min_x) as f32 - style.grout;
+		let y_len = (max_y - min_y) as f32 - style.grout;
+		if x_len <= 0_f32 || y_len <= 0_f32 { continue; }
+
+		let radius = style.corner_radius * x_len;
+		let inner_radius = (radius - style.border).max(0_f32);
+
+		let card = &renderer.card_images[card_grid.grid[tile_index] as usize];
+		let variant = card_grid.variants[tile_index];
+		let (tile_width, tile_height) = ((x_len.round() as u32).max(1), (y_len.round() as u32).max(1));
+		let tile_key = (card.path.clone(), variant.window.to_bits(), tile_width, tile_height);
+
+		if !renderer.tile_images.contains_key(&tile_key) {
+			let card_aspect = renderer.card_aspect;
+			let full_image = renderer.loaded_cards
+				.entry(card.path.clone())
+				.or_insert_with(|| load_card_image(card, card_aspect));
+
+			let tile_image = resize_tile_image(full_image, card_aspect, variant.window, tile_width, tile_height, renderer.kernel);
+			renderer.tile_images.insert(tile_key.clone(), tile_image);
+		}
+
+		let tile_bytes = renderer.tile_images[&tile_key].as_bytes();
+
+		for draw_x in min_x.max(0)..max_x.min(image_width as i32) {
+			for draw_y in min_y.max(from_y as i32)..max_y.min(to_y as i32) {
+				/* test the center of the pixel against the tile's shape */
+				let (center_x, center_y) = (draw_x as f32 + 0.5_f32, draw_y as f32 + 0.5_f32);
+				if !tile_contains(tile, center_x / card_width, center_y / card_height) { continue; }
+
+				let (draw_x, strip_y) = (draw_x as u32, draw_y as u32 - from_y);
+				let (x_along, y_along) = (center_x - card_min_x, center_y - card_min_y);
+				let edge_distance = if edged { tile_edge_distance(tile, center_x, center_y, card_width, card_height) } else { f32::MAX };
+
+				if edge_distance < inset || (radius > 0_f32 && outside_corner(x_along, y_along, x_len, y_len, radius)) {
+					put_pixel(&mut draw_bytes, &grout_color, channels, image_width, draw_x, strip_y);
+					continue;
+				}
+
+				if style.border > 0_f32 && (
+					edge_distance < inset + style.border ||
+					outside_corner(x_along - style.border, y_along - style.border, x_len - style.border * 2_f32, y_len - style.border * 2_f32, inner_radius)
+				) {
+					put_pixel(&mut draw_bytes, &border_color, channels, image_width, draw_x, strip_y);
+					continue;
+				}
+
+				/* the resized card is the tile's size, so each drawn pixel is one of its pixels */
+				let tile_x = ((x_along / x_len) * tile_width as f32).floor().max(0_f32).min((tile_width - 1) as f32);
+				let tile_y = ((y_along / y_len) * tile_height as f32).floor().max(0_f32).min((tile_height - 1) as f32);
+				let (tile_x, tile_y) = transform_point(variant.transform, tile_x, tile_y, tile_width as f32, tile_height as f32);
+
+				let offset = ((tile_y as u32 * tile_width + tile_x as u32) * 4) as usize;
+				put_pixel(&mut draw_bytes, &[tile_bytes[offset], tile_bytes[offset + 1], tile_bytes[offset + 2], tile_bytes[offset + 3]], channels, image_width, draw_x, strip_y);
+			}
+		}
+	}
+
+	/* files with no tiles starting past this strip have been resized for every tile using them */
+	let last_starts = &renderer.last_starts;
+	renderer.loaded_cards.retain(|path, _| last_starts.get(path.as_path()).is_some_and(|last_start| *last_start >= to_y as i32));
+
+	/* cards that don't reach past this strip won't be drawn again */
+	let last_rows = &renderer.last_rows;
+	renderer.tile_images.retain(|(path, _, _, _), _| last_rows.get(path.as_path()).is_some_and(|last_row| *last_row > to_y as i32));
+
+	draw_bytes
+}
@@ -0,0 +1,90 @@
+use image::RgbImage;
+use image::imageops::{flip_horizontal, flip_vertical, rotate180};
+
+/**
+ * ways a card can be turned while keeping its shape
+ */
+#[derive(Clone, Copy, PartialEq)]
+pub enum Transform {
+	Original,
+	FlipHorizontal,
+	Rotate180,
+	/* flipped horizontally then rotated 180 */
+	FlipVertical,
+}
+
+/**
+ * which extra orientations each card is tried in
+ */
+#[derive(Clone, Copy, PartialEq)]
+pub enum Augment {
+	None,
+	Mirror,
+	Rotate,
+}
+
+/**
+ * how a transform is written out, in manifests for one
+ */
+pub fn transform_name(transform: Transform) -> &'static str {
+	match transform {
+		Transform::Original => "original",
+		Transform::FlipHorizontal => "flip-horizontal",
+		Transform::Rotate180 => "rotate-180",
+		Transform::FlipVertical => "flip-vertical",
+	}
+}
+
+pub fn parse_transform(name: &str) -> Option<Transform> {
+	match name {
+		"original" => Some(Transform::Original),
+		"flip-horizontal" => Some(Transform::FlipHorizontal),
+		"rotate-180" => Some(Transform::Rotate180),
+		"flip-vertical" => Some(Transform::FlipVertical),
+		_ => None,
+	}
+}
+
+pub fn parse_augment(name: &str) -> Option<Augment> {
+	match name {
+		"none" => Some(Augment::None),
+		"mirror" => Some(Augment::Mirror),
+		"rotate" => Some(Augment::Rotate),
+		_ => None,
+	}
+}
+
+/**
+ * turning upside down keeps any tile's shape, so rotation works for every card aspect
+ */
+pub fn augment_transforms(augment: Augment) -> Vec<Transform> {
+	match augment {
+		Augment::None => vec![Transform::Original],
+		Augment::Mirror => vec![Transform::Original, Transform::FlipHorizontal],
+		Augment::Rotate => vec![Transform::Original, Transform::FlipHorizontal, Transform::Rotate180, Transform::FlipVertical],
+	}
+}
+
+pub fn apply_transform(image: &RgbImage, transform: Transform) -> RgbImage {
+	match transform {
+		Transform::Original => image.clone(),
+		Transform::FlipHorizontal => flip_horizontal(image),
+		Transform::Rotate180 => rotate180(image),
+		Transform::FlipVertical => flip_vertical(image),
+	}
+}
+
+/**
+ * where a point in the transformed card comes from in the original card
+ */
+pub fn transform_point(transform: Transform, x: f32, y: f32, width: f32, height: f32) -> (f32, f32) {
+	let flip_x = |x: f32| (width - 1.0_f32 - x).max(0.0_f32);
+	let flip_y = |y: f32| (height - 1.0_f32 - y).max(0.0_f32);
+
+	match transform {
+		Transform::Original => (x, y),
+		Transform::FlipHorizontal => (flip_x(x), y),
+		Transform::Rotate180 => (flip_x(x), flip_y(y)),
+		Transform::FlipVertical => (x, flip_y(y)),
+	}
+}
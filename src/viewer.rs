@@ -0,0 +1,146 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use crate::CardGrid;
+use crate::library::LibraryCard;
+use crate::metadata::{CardMetadata, card_metadata};
+use crate::output::OutputFormat;
+
+const OPENSEADRAGON_URL: &str = "https://cdnjs.cloudflare.com/ajax/libs/openseadragon/4.1.0/openseadragon.min.js";
+
+/**
+ * a tile as the page sees it, its polygon in fractions of the image's width and height
+ */
+#[derive(Serialize)]
+struct ViewerTile<'a> {
+	polygon: Vec<(f32, f32)>,
+	file: String,
+	card: Option<&'a CardMetadata>,
+}
+
+/* {{SOURCE}}, {{ZOOM}} and {{TILES}} are filled in by write_viewer */
+const VIEWER_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Mosaic</title>
+<style>
+	body { margin: 0; display: flex; height: 100vh; font-family: sans-serif; background: #111; color: #eee; }
+	#view { flex: 1; position: relative; overflow: auto; }
+	#view img { width: 100%; display: block; cursor: crosshair; }
+	#info { width: 18em; padding: 1em; background: #222; }
+	#info a { color: #8cf; }
+</style>
+</head>
+<body>
+<div id="view"></div>
+<div id="info">Hover over a card</div>
+<script>
+const SOURCE = {{SOURCE}};
+const ZOOM = {{ZOOM}};
+const TILES = {{TILES}};
+
+/* crossing number test, same as tile_contains */
+function contains(polygon, x, y) {
+	let inside = false;
+	for (let current = 0, last = polygon.length - 1; current < polygon.length; last = current++) {
+		const [x0, y0] = polygon[current];
+		const [x1, y1] = polygon[last];
+		if ((y0 > y) != (y1 > y) && x < (x1 - x0) * (y - y0) / (y1 - y0) + x0) inside = !inside;
+	}
+	return inside;
+}
+
+function escape(text) {
+	const element = document.createElement("span");
+	element.textContent = text;
+	return element.innerHTML;
+}
+
+function show(x, y) {
+	const tile = TILES.find(tile => contains(tile.polygon, x, y));
+	const info = document.getElementById("info");
+	if (!tile) return;
+
+	if (!tile.card) {
+		info.innerHTML = "<h3>" + escape(tile.file) + "</h3>";
+		return;
+	}
+
+	const card = tile.card;
+	info.innerHTML =
+		"<h3>" + escape(card.name) + "</h3>" +
+		"<p>" + escape(card.set_name) + " (" + escape(card.set.toUpperCase()) + ") #" + escape(card.collector_number) + "</p>" +
+		(card.artist ? "<p>Illustrated by " + escape(card.artist) + "</p>" : "") +
+		"<p><a href=\"" + encodeURI(card.scryfall_uri) + "\" target=\"_blank\">View on Scryfall</a></p>";
+}
+
+if (ZOOM) {
+	const script = document.createElement("script");
+	script.src = "{{OPENSEADRAGON}}";
+	script.onload = () => {
+		const viewer = OpenSeadragon({ element: document.getElementById("view"), tileSources: SOURCE, showNavigationControl: false });
+		const locate = position => {
+			const size = viewer.world.getItemAt(0).getContentSize();
+			const point = viewer.viewport.viewerElementToImageCoordinates(position);
+			show(point.x / size.x, point.y / size.y);
+		};
+		new OpenSeadragon.MouseTracker({ element: viewer.element, moveHandler: event => locate(event.position) });
+	};
+	document.head.appendChild(script);
+} else {
+	const image = document.createElement("img");
+	image.src = SOURCE;
+	image.addEventListener("mousemove", event => {
+		const bounds = image.getBoundingClientRect();
+		show((event.clientX - bounds.left) / bounds.width, (event.clientY - bounds.top) / bounds.height);
+	});
+	document.getElementById("view").appendChild(image);
+}
+</script>
+</body>
+</html>
+"#;
+
+/**
+ * a static page next to the output that names the card under the cursor
+ * deep zoom and iiif outputs are shown with openseadragon, anything else as a plain image
+ */
+pub fn write_viewer(
+	path: &Path,
+	output_path: &Path,
+	output_format: OutputFormat,
+	card_grid: &CardGrid,
+	card_images: &[LibraryCard],
+	metadata: &HashMap<String, CardMetadata>,
+) -> std::io::Result<()> {
+	let tiles = card_grid.tiles.iter().enumerate().map(|(tile_index, tile)| {
+		let card = &card_images[card_grid.grid[tile_index] as usize];
+
+		ViewerTile {
+			polygon: tile.polygon.iter()
+				.map(|(x, y)| (x / card_grid.cards_wide as f32, y / card_grid.cards_tall as f32))
+				.collect::<Vec<(f32, f32)>>(),
+			file: card.path.file_name().unwrap().to_string_lossy().into_owned(),
+			card: card_metadata(metadata, &card.path),
+		}
+	}).collect::<Vec<ViewerTile>>();
+
+	/* the page sits next to the output so it can refer to it by name */
+	let output_name = output_path.file_name().unwrap().to_string_lossy().into_owned();
+	let (source, zoom) = match output_format {
+		OutputFormat::DeepZoom => (output_name, true),
+		OutputFormat::Iiif => (format!("{}/info.json", output_name), true),
+		_ => (output_name, false),
+	};
+
+	let page = VIEWER_PAGE
+		.replace("{{SOURCE}}", &serde_json::to_string(&source)?)
+		.replace("{{ZOOM}}", if zoom { "true" } else { "false" })
+		.replace("{{OPENSEADRAGON}}", OPENSEADRAGON_URL)
+		/* keep card names from closing the script early */
+		.replace("{{TILES}}", &serde_json::to_string(&tiles)?.replace("</", "<\\/"));
+
+	fs::write(path, page)
+}
@@ -0,0 +1,93 @@
+use image::{DynamicImage, GenericImageView};
+use crate::transform::Transform;
+
+/**
+ * one way of cutting a card down to a tile
+ * window is how far along the card's spare length the tile aspect crop sits,
+ * 0 is the left or top edge, 0.5 the center, 1 the right or bottom edge
+ */
+#[derive(Clone, Copy, PartialEq)]
+pub struct Variant {
+	pub window: f32,
+	pub transform: Transform,
+}
+
+pub const CENTER_VARIANT: Variant = Variant { window: 0.5_f32, transform: Transform::Original };
+
+/**
+ * evenly spaced windows, 1 is a plain center crop and 3 is left, center, right
+ */
+pub fn window_offsets(count: u32) -> Vec<f32> {
+	if count <= 1 { return vec![0.5_f32]; }
+
+	(0..count).map(|index| index as f32 / (count - 1) as f32).collect::<Vec<f32>>()
+}
+
+/**
+ * every window in every transform
+ */
+pub fn create_variants(windows: &[f32], transforms: &[Transform]) -> Vec<Variant> {
+	windows.iter().flat_map(|window| transforms.iter().map(move |transform| Variant {
+		window: *window,
+		transform: *transform,
+	})).collect::<Vec<Variant>>()
+}
+
+/**
+ * (x, y, width, height) of the largest card_aspect rectangle in an image,
+ * slid along whichever side has room to spare
+ */
+pub fn window_rect(width: f32, height: f32, card_aspect: f32, window: f32) -> (f32, f32, f32, f32) {
+	if width / height > card_aspect {
+		let window_width = height * card_aspect;
+		((width - window_width) * window, 0.0_f32, window_width, height)
+
+	} else {
+		let window_height = width / card_aspect;
+		(0.0_f32, (height - window_height) * window, width, window_height)
+	}
+}
+
+pub fn crop_window(image: &DynamicImage, card_aspect: f32, window: f32) -> DynamicImage {
+	let (x, y, width, height) = window_rect(image.width() as f32, image.height() as f32, card_aspect, window);
+
+	image.crop_imm(
+		x.round() as u32,
+		y.round() as u32,
+		(width.round() as u32).max(1),
+		(height.round() as u32).max(1),
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn windows_slide_along_spare_width() {
+		/* square tiles out of a wide image */
+		let rects = window_offsets(3).iter()
+			.map(|window| window_rect(626_f32, 300_f32, 1_f32, *window))
+			.collect::<Vec<(f32, f32, f32, f32)>>();
+
+		assert_eq!(rects[0], (0_f32, 0_f32, 300_f32, 300_f32));
+		assert_eq!(rects[1], (163_f32, 0_f32, 300_f32, 300_f32));
+		assert_eq!(rects[2], (326_f32, 0_f32, 300_f32, 300_f32));
+	}
+
+	#[test]
+	fn windows_slide_along_spare_height() {
+		let rects = window_offsets(2).iter()
+			.map(|window| window_rect(626_f32, 457_f32, 16_f32 / 9_f32, *window))
+			.collect::<Vec<(f32, f32, f32, f32)>>();
+
+		assert_ne!(rects[0], rects[1]);
+		assert_eq!(rects[0].1, 0_f32);
+		assert!((rects[1].1 + rects[1].3 - 457_f32).abs() < 0.01_f32);
+	}
+
+	#[test]
+	fn card_aspect_images_have_one_window() {
+		assert_eq!(window_rect(160_f32, 90_f32, 16_f32 / 9_f32, 0_f32), window_rect(160_f32, 90_f32, 16_f32 / 9_f32, 1_f32));
+	}
+}